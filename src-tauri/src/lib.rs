@@ -1,32 +1,105 @@
-use modules::db::{BichoGroup, LossSequence};
-use tauri::{AppHandle, Emitter};
+use chrono::{Duration, Utc};
+use modules::conn::DbLocation;
+use modules::analysis::DroughtPoint;
+use modules::db::{BichoGroup, GroupDroughtStats, HouseQuality, LossSequence, ReingestReport};
+use modules::jobs::JobInfo;
+use modules::progress::{Progress, ProgressSink};
+use modules::repo::{DrawRevision, QuarantinedDraw, RawPayloadInfo};
+use modules::source::ResultSource;
+use modules::sync::{HouseSyncResult, ReprocessSummary, SyncSummary};
+use modules::workspace::{AppDirs, Workspace};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, Manager};
 
 mod modules;
 
 #[tauri::command]
-async fn houses() -> String {
-    let houses = modules::web::get_houses()
-        .await
-        .expect("erro ao pegar as casas");
+async fn houses(source: Option<String>) -> Result<String, String> {
+    let source = modules::source::select_source(source.as_deref())?;
+    let houses = match modules::sync::load_houses(source.as_ref()).await {
+        Ok(houses) => houses,
+        Err(err) => {
+            println!("Erro ao pegar as casas: {:?}", err);
+            return Err(err);
+        }
+    };
 
     let json = serde_json::to_string(&houses).expect("Erro ao serializar o json");
 
-    json
+    Ok(json)
 }
 
 #[tauri::command]
-async fn get_database_info(house_name: String) -> Result<String, ()> {
-    match modules::db::get_table_info(house_name) {
+async fn get_database_info(lottery: String) -> Result<String, ()> {
+    match modules::db::get_table_info(lottery) {
         Ok(database_info) => {
             let json =
                 serde_json::to_string(&database_info).expect("Erro ao serializar o database info");
 
-            return Ok(json);
-        }
-        Err(_) => {
-            return Err(());
+            Ok(json)
         }
+        Err(_) => Err(()),
+    }
+}
+
+#[tauri::command]
+fn get_database_path() -> Result<DbLocation, String> {
+    modules::conn::db_location().ok_or("O banco de dados ainda não foi aberto".to_string())
+}
+
+fn app_dirs(app_handle: &AppHandle) -> Result<AppDirs, String> {
+    Ok(AppDirs {
+        data_dir: app_handle.path().app_data_dir().map_err(|err| err.to_string())?,
+        config_dir: app_handle.path().app_config_dir().map_err(|err| err.to_string())?,
+    })
+}
+
+// Grava `path` como o caminho do banco principal nas configurações e volta
+// para ele. Sem `path`, usa o padrão. `BICHO_DB_PATH` continua tendo prioridade.
+#[tauri::command]
+fn set_database_path(app_handle: AppHandle, path: Option<String>) -> Result<DbLocation, String> {
+    if modules::jobs::has_running_jobs() {
+        return Err("Espere as sincronizações em andamento terminarem para trocar o banco".to_string());
     }
+
+    let dirs = app_dirs(&app_handle)?;
+    let mut settings = modules::settings::load(&dirs.config_dir)?;
+    settings.db_path = path.filter(|path| !path.is_empty()).map(PathBuf::from);
+    settings.workspace = None;
+
+    // Só salva a configuração se o banco novo abrir
+    let location = modules::conn::resolve_db_path(&dirs.data_dir, &settings);
+    modules::conn::open_db(location.clone())?;
+    modules::settings::save(&dirs.config_dir, &settings)?;
+
+    Ok(location)
+}
+
+#[tauri::command]
+fn list_workspaces(app_handle: AppHandle) -> Result<Vec<Workspace>, String> {
+    modules::workspace::list(&app_dirs(&app_handle)?)
+}
+
+#[tauri::command]
+fn create_workspace(app_handle: AppHandle, name: String) -> Result<Workspace, String> {
+    modules::workspace::create(&app_dirs(&app_handle)?, &name)
+}
+
+#[tauri::command]
+fn switch_workspace(app_handle: AppHandle, name: String) -> Result<Workspace, String> {
+    modules::workspace::switch(&app_dirs(&app_handle)?, &name)
+}
+
+#[tauri::command]
+fn rename_workspace(app_handle: AppHandle, name: String, new_name: String) -> Result<Workspace, String> {
+    modules::workspace::rename(&app_dirs(&app_handle)?, &name, &new_name)
+}
+
+#[tauri::command]
+fn delete_workspace(app_handle: AppHandle, name: String) -> Result<(), String> {
+    modules::workspace::delete(&app_dirs(&app_handle)?, &name)
 }
 
 #[tauri::command]
@@ -35,32 +108,120 @@ async fn get_bichos_data(
     house_name: String,
     lottery: String,
     total_days: i32,
+    source: Option<String>,
 ) -> Result<String, String> {
-    println!("{}", total_days);
-    match modules::web::get_bichos_data(lottery, total_days).await {
-        Ok(json) => {
-            match modules::db::save_bicho_data(house_name, &json, |progress| {
-                app_handle.emit("progress", progress).expect("Falha ao emitir evento");
-            }) {
-                Ok(_) => {}
-                Err(err) => {
-                    println!("Erro salvar data: {:?}", err);
-                    return Err(err.to_string());
-                }
-            }
-
-            return Ok("Dados salvado com sucesso".to_string());
+    let source = modules::source::select_source(source.as_deref())?;
+    let to = Utc::now().date_naive();
+    let from = to - Duration::days(total_days as i64 - 1);
+
+    let sink = EventSink(app_handle);
+
+    match modules::sync::sync_range(source.as_ref(), &house_name, &lottery, from, to, &sink).await {
+        Ok(_) => Ok("Dados salvado com sucesso".to_string()),
+        Err(err) => {
+            println!("Erro ao sincronizar: {:?}", err);
+            Err(err)
         }
+    }
+}
+
+#[tauri::command]
+async fn sync_house(
+    app_handle: AppHandle,
+    house_name: String,
+    lottery: String,
+    full: Option<bool>,
+    source: Option<String>,
+) -> Result<SyncSummary, String> {
+    let source = modules::source::select_source(source.as_deref())?;
+
+    let sink = EventSink(app_handle);
+
+    match modules::sync::sync_house(source.as_ref(), &house_name, &lottery, full.unwrap_or(false), &sink).await {
+        Ok(summary) => Ok(summary),
         Err(err) => {
-            println!("Erro pegar data: {:?}", err);
-            return Err(err.to_string());
+            println!("Erro ao sincronizar: {:?}", err);
+            Err(err)
         }
     }
 }
 
+// Envia o progresso de cada job em um evento próprio (`progress:<job_id>`),
+// para que sincronizações paralelas apareçam separadas no frontend.
+struct EventSink(AppHandle);
+
+#[derive(Clone, Serialize)]
+struct JobStarted {
+    job_id: u64,
+    house: String,
+}
+
+impl ProgressSink for EventSink {
+    fn job_started(&self, job_id: u64, house: &str) {
+        let payload = JobStarted {
+            job_id,
+            house: house.to_string(),
+        };
+        self.0.emit("job-started", payload).expect("Falha ao emitir evento");
+    }
+
+    fn progress(&self, progress: &Progress) {
+        self.0
+            .emit(&format!("progress:{}", progress.job_id), progress.clone())
+            .expect("Falha ao emitir evento");
+    }
+}
+
+// Respostas da fonte guardadas no arquivo. Sem `lottery`, de todas as casas.
 #[tauri::command]
-fn export_csv(house_name: String, file_path: String) -> Result<(), String> {
-    match modules::db::export_table_to_csv(house_name, &file_path) {
+fn list_raw_payloads(lottery: Option<String>) -> Result<Vec<RawPayloadInfo>, String> {
+    modules::archive::list(lottery)
+}
+
+// Grava de novo os sorteios da casa a partir do arquivo (todas as respostas ou só `ids`)
+#[tauri::command]
+async fn reprocess_raw(
+    app_handle: AppHandle,
+    house_name: String,
+    lottery: String,
+    ids: Option<Vec<i64>>,
+) -> Result<ReprocessSummary, String> {
+    let sink = EventSink(app_handle);
+
+    modules::sync::reprocess_raw(&house_name, &lottery, ids, &sink).await
+}
+
+#[tauri::command]
+async fn sync_all_houses(
+    app_handle: AppHandle,
+    full: Option<bool>,
+    concurrency: Option<usize>,
+    source: Option<String>,
+) -> Result<Vec<HouseSyncResult>, String> {
+    let source: Arc<dyn ResultSource> = modules::source::select_source(source.as_deref())?.into();
+
+    modules::sync::sync_all_houses(
+        source,
+        full.unwrap_or(false),
+        concurrency.unwrap_or(modules::sync::SYNC_CONCURRENCY),
+        Arc::new(EventSink(app_handle)),
+    )
+    .await
+}
+
+#[tauri::command]
+fn cancel_job(id: u64) -> Result<(), String> {
+    modules::jobs::cancel_job(id)
+}
+
+#[tauri::command]
+fn list_jobs() -> Vec<JobInfo> {
+    modules::jobs::list_jobs()
+}
+
+#[tauri::command]
+fn export_csv(lottery: String, file_path: String) -> Result<(), String> {
+    match modules::db::export_table_to_csv(lottery, &file_path) {
         Ok(_) => {}
         Err(err) => {
             return Err(err.to_string());
@@ -71,8 +232,8 @@ fn export_csv(house_name: String, file_path: String) -> Result<(), String> {
 }
 
 #[tauri::command]
-fn get_group(house_name: String) -> Result<String, String> {
-    let groups = match modules::db::get_groups(house_name) {
+fn get_group(lottery: String) -> Result<String, String> {
+    let groups = match modules::db::get_groups(lottery) {
         Ok(hours) => hours,
         Err(err) => {
             println!("{:?}", err);
@@ -87,45 +248,107 @@ fn get_group(house_name: String) -> Result<String, String> {
 }
 
 #[tauri::command]
-fn get_hours(house_name: String) -> Result<Vec<String>, String> {
-    match modules::db::get_hours(house_name) {
+fn get_hours(lottery: String) -> Result<Vec<String>, String> {
+    match modules::db::get_hours(lottery) {
         Ok(hours) => Ok(hours),
         Err(err) => Err(err.to_string())
     }
 }
 
 #[tauri::command]
-fn get_places(house_name: String) -> Result<Vec<u32>, String> {
-    match modules::db::get_places(house_name) {
+fn get_places(lottery: String) -> Result<Vec<u32>, String> {
+    match modules::db::get_places(lottery) {
         Ok(places) => Ok(places),
         Err(err) => Err(err.to_string())
     }
 }
 
 #[tauri::command]
-fn add_group(house_name: String, data: BichoGroup) -> Result<(), String> {
-    modules::db::add_group(house_name, data)?;
+fn add_group(lottery: String, data: BichoGroup) -> Result<(), String> {
+    modules::db::add_group(lottery, data)?;
+
+    Ok(())
+}
+
+#[tauri::command]
+fn edit_group(lottery: String, data: BichoGroup) -> Result<(), String> {
+    modules::db::edit_group(lottery, data)?;
 
     Ok(())
 }
 
 #[tauri::command]
-fn edit_group(house_name: String, data: BichoGroup) -> Result<(), String> {
-    modules::db::edit_group(house_name, data)?;
+fn delete_group(lottery: String, id: u32) -> Result<(), String> {
+    modules::db::delete_group(lottery, id)?;
 
     Ok(())
 }
 
+// Maior seca, média e percentis das secas de cada grupo cadastrado da casa
 #[tauri::command]
-fn delete_group(house_name: String, id: u32) -> Result<(), String> {
-    modules::db::delete_group(house_name, id)?;
+fn get_drought_stats(lottery: String) -> Result<Vec<GroupDroughtStats>, String> {
+    modules::db::get_drought_stats(lottery)
+}
+
+// Cada vez que o grupo deu, com quantos sorteios sem ele vieram antes
+#[tauri::command]
+fn get_drought_timeline(lottery: String, data: BichoGroup) -> Result<Vec<DroughtPoint>, String> {
+    modules::db::get_drought_timeline(lottery, &data)
+}
+
+#[tauri::command]
+fn export_drought_timeline(lottery: String, data: BichoGroup, file_path: String) -> Result<(), String> {
+    modules::db::export_drought_timeline_to_csv(lottery, &data, &file_path).map_err(|err| err.to_string())
+}
+
+// Sorteios cujo grupo do site não bate com o milhar. Sem `lottery`, de todas as casas.
+#[tauri::command]
+fn get_data_quality_report(lottery: Option<String>) -> Result<Vec<HouseQuality>, String> {
+    modules::db::data_quality_report(lottery)
+}
+
+// Correções que o site fez em sorteios já salvos. Sem `lottery`, de todas as casas.
+#[tauri::command]
+fn get_draw_revisions(lottery: Option<String>) -> Result<Vec<DrawRevision>, String> {
+    modules::db::get_draw_revisions(lottery)
+}
+
+// Sorteios rejeitados pelo modo estrito. Sem `lottery`, de todas as casas.
+#[tauri::command]
+fn list_quarantine(lottery: Option<String>) -> Result<Vec<QuarantinedDraw>, String> {
+    modules::db::list_quarantine(lottery)
+}
+
+// Tenta gravar de novo os sorteios da quarentena. Sem `strict`, usa a configuração atual.
+#[tauri::command]
+fn reingest_quarantine(
+    lottery: String,
+    ids: Option<Vec<i64>>,
+    strict: Option<bool>,
+) -> Result<ReingestReport, String> {
+    let strict = strict.unwrap_or_else(modules::db::strict_ingestion);
+
+    modules::db::reingest_quarantine(lottery, ids, strict)
+}
+
+#[tauri::command]
+fn set_strict_ingestion(app_handle: AppHandle, enabled: bool) -> Result<(), String> {
+    let dirs = app_dirs(&app_handle)?;
+    let mut settings = modules::settings::load(&dirs.config_dir)?;
+    settings.strict_ingestion = enabled;
+    modules::settings::save(&dirs.config_dir, &settings)?;
+
+    modules::db::set_strict_ingestion(enabled);
 
     Ok(())
 }
 
+// Sem `as_of`, até o último sorteio. Com ele (aaaa-mm-dd, aaaa-mm-dd HH:MM ou
+// timestamp), como estava naquele momento.
 #[tauri::command]
-fn get_loss_sequence(house_name: String) -> Result<Vec<LossSequence>, String> {
-    let loss_sequence = modules::db::get_loss_sequence(house_name)?;
+fn get_loss_sequence(lottery: String, as_of: Option<String>) -> Result<Vec<LossSequence>, String> {
+    let as_of = as_of.as_deref().map(modules::db::parse_as_of).transpose()?;
+    let loss_sequence = modules::db::get_loss_sequence(lottery, as_of)?;
 
     Ok(loss_sequence)
 }
@@ -134,12 +357,35 @@ fn get_loss_sequence(house_name: String) -> Result<Vec<LossSequence>, String> {
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
+        .setup(|app| {
+            let dirs = app_dirs(app.handle())?;
+            let settings = modules::settings::load(&dirs.config_dir)?;
+
+            // Não abre o app com um banco que ele não sabe ler
+            modules::conn::open_db(modules::workspace::active_location(&dirs, &settings))?;
+            modules::db::set_strict_ingestion(settings.strict_ingestion);
+
+            Ok(())
+        })
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_shell::init())
         .invoke_handler(tauri::generate_handler![
             houses,
             get_database_info,
+            get_database_path,
+            set_database_path,
+            list_workspaces,
+            create_workspace,
+            switch_workspace,
+            rename_workspace,
+            delete_workspace,
             get_bichos_data,
+            sync_house,
+            sync_all_houses,
+            list_raw_payloads,
+            reprocess_raw,
+            cancel_job,
+            list_jobs,
             export_csv,
             get_hours,
             get_places,
@@ -148,6 +394,14 @@ pub fn run() {
             edit_group,
             delete_group,
             get_loss_sequence,
+            get_drought_stats,
+            get_drought_timeline,
+            export_drought_timeline,
+            get_data_quality_report,
+            get_draw_revisions,
+            list_quarantine,
+            reingest_quarantine,
+            set_strict_ingestion,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,274 @@
+use super::repo::HistoryRow;
+use chrono::{DateTime, NaiveDate};
+use serde::Serialize;
+use std::collections::HashMap;
+
+// Sorteio de um horário e prêmio
+#[derive(Debug, Clone, Copy)]
+pub struct DrawPoint {
+    pub date: i64,
+    pub group: i64,
+}
+
+// Sorteios de cada horário e prêmio de uma loteria, em ordem de data
+pub struct History {
+    series: HashMap<(String, u32), Vec<DrawPoint>>,
+}
+
+impl History {
+    // `rows` na ordem do `DrawRepo::history`: horário, prêmio e data
+    pub fn new(rows: Vec<HistoryRow>) -> Self {
+        let mut series: HashMap<(String, u32), Vec<DrawPoint>> = HashMap::new();
+        for row in rows {
+            series.entry((row.hour, row.place)).or_default().push(DrawPoint {
+                date: row.date,
+                group: row.group,
+            });
+        }
+
+        History { series }
+    }
+
+    pub fn series(&self, hour: &str, place: u32) -> &[DrawPoint] {
+        self.series
+            .get(&(hour.to_string(), place))
+            .map_or(&[], |series| series.as_slice())
+    }
+}
+
+// Secas de um conjunto de grupos numa série
+#[derive(Debug, Clone, PartialEq)]
+pub struct Droughts {
+    // Quantos sorteios seguidos sem dar nenhum dos grupos antes de cada vez que deu
+    pub completed: Vec<usize>,
+    // Seca em andamento. `None` se os grupos nunca deram.
+    pub current: Option<usize>,
+}
+
+// Posição de cada sorteio da série que deu um dos `groups` e quantos
+// sorteios seguidos sem eles vieram antes
+fn hits(series: &[DrawPoint], groups: &[u32]) -> Vec<(usize, usize)> {
+    let mut hits = Vec::new();
+    let mut run = 0;
+
+    for (index, draw) in series.iter().enumerate() {
+        if groups.iter().any(|&group| group as i64 == draw.group) {
+            hits.push((index, run));
+            run = 0;
+        } else {
+            run += 1;
+        }
+    }
+
+    hits
+}
+
+pub fn droughts(series: &[DrawPoint], groups: &[u32]) -> Droughts {
+    let hits = hits(series, groups);
+
+    Droughts {
+        // A seca antes da primeira vez começou antes dos dados salvos, então não conta
+        completed: hits.iter().skip(1).map(|&(_, run)| run).collect(),
+        current: hits.last().map(|&(index, _)| series.len() - 1 - index),
+    }
+}
+
+// Uma vez que o grupo deu, com a seca que acabou ali
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DroughtPoint {
+    pub date: NaiveDate,
+    pub draws_before: usize,
+    // Falso na primeira vez, cuja seca começou antes dos dados salvos
+    pub complete: bool,
+}
+
+pub fn timeline(series: &[DrawPoint], groups: &[u32]) -> Vec<DroughtPoint> {
+    hits(series, groups)
+        .into_iter()
+        .enumerate()
+        .map(|(position, (index, run))| DroughtPoint {
+            date: DateTime::from_timestamp(series[index].date, 0)
+                .map_or(NaiveDate::MIN, |datetime| datetime.date_naive()),
+            draws_before: run,
+            complete: position > 0,
+        })
+        .collect()
+}
+
+// Grupos que existem no jogo do bicho
+const GROUP_COUNT: usize = 25;
+
+// Chance de uma seca de ao menos `drought` sorteios com `groups` grupos,
+// se cada sorteio dá um deles com chance k/25. Grupos fora de 1 a 25 nunca
+// saem, então não contam.
+pub fn theoretical_probability(groups: &[u32], drought: usize) -> f64 {
+    let mut distinct: Vec<u32> = groups
+        .iter()
+        .copied()
+        .filter(|group| (1..=GROUP_COUNT as u32).contains(group))
+        .collect();
+    distinct.sort_unstable();
+    distinct.dedup();
+    let hit = distinct.len() as f64 / GROUP_COUNT as f64;
+
+    (1.0 - hit).powf(drought as f64)
+}
+
+// Fração das secas terminadas com ao menos `drought` sorteios. `None` sem nenhuma.
+pub fn empirical_probability(completed: &[usize], drought: usize) -> Option<f64> {
+    if completed.is_empty() {
+        return None;
+    }
+    let longer = completed.iter().filter(|&&run| run >= drought).count();
+
+    Some(longer as f64 / completed.len() as f64)
+}
+
+// Secas terminadas a partir das quais a chance histórica da casa vale mais
+// que a teórica
+const MIN_COMPLETED_DROUGHTS: usize = 30;
+
+// Chance de que a surpresa sai: a histórica quando há secas terminadas
+// suficientes, senão a teórica. A seca atual entra na conta da histórica
+// como mais uma, para ela não dar 0 quando passa de todas as outras.
+pub fn ranking_probability(theoretical: f64, completed: &[usize], drought: usize) -> f64 {
+    if completed.len() < MIN_COMPLETED_DROUGHTS {
+        return theoretical;
+    }
+    let longer = completed.iter().filter(|&&run| run >= drought).count();
+
+    (longer + 1) as f64 / (completed.len() + 1) as f64
+}
+
+// Quanto mais rara a seca, maior: -log10 da chance, 0 para uma seca certa e
+// 1 a mais a cada vez que a chance cai dez vezes. Chance 0 (sorteios sem
+// grupo válido com os 25 grupos) fica no maior valor finito, que o JSON aceita.
+pub fn surprise(probability: f64) -> f64 {
+    probability.max(f64::MIN_POSITIVE).recip().log10()
+}
+
+// Resumo das secas terminadas. Sem nenhuma, só `count` vem preenchido.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DroughtStats {
+    pub count: usize,
+    pub max: Option<usize>,
+    pub mean: Option<f64>,
+    pub p50: Option<usize>,
+    pub p90: Option<usize>,
+    pub p95: Option<usize>,
+    pub p99: Option<usize>,
+}
+
+pub fn drought_stats(completed: &[usize]) -> DroughtStats {
+    let mut sorted = completed.to_vec();
+    sorted.sort_unstable();
+    let mean = (!sorted.is_empty()).then(|| sorted.iter().sum::<usize>() as f64 / sorted.len() as f64);
+
+    DroughtStats {
+        count: sorted.len(),
+        max: sorted.last().copied(),
+        mean,
+        p50: percentile(&sorted, 50),
+        p90: percentile(&sorted, 90),
+        p95: percentile(&sorted, 95),
+        p99: percentile(&sorted, 99),
+    }
+}
+
+// Percentil pelo posto mais próximo: o menor valor com ao menos `percent`% dos valores até ele
+fn percentile(sorted: &[usize], percent: usize) -> Option<usize> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (percent * sorted.len()).div_ceil(100).max(1);
+
+    Some(sorted[rank - 1])
+}
+
+#[cfg(test)]
+mod testes {
+    use super::*;
+
+    // Um sorteio por dia a partir de 01/02/2024
+    fn series(groups: &[i64]) -> Vec<DrawPoint> {
+        groups
+            .iter()
+            .enumerate()
+            .map(|(day, &group)| DrawPoint {
+                date: 1706745600 + day as i64 * 86400,
+                group,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn droughts_ignore_the_run_before_the_first_hit() {
+        let draws = series(&[1, 5, 2, 3, 5, 5, 4, 9, 4, 5, 8, 8]);
+
+        assert_eq!(
+            droughts(&draws, &[5]),
+            Droughts {
+                completed: vec![2, 0, 3],
+                current: Some(2),
+            }
+        );
+        assert_eq!(droughts(&draws, &[4, 9]).completed, vec![0, 0]);
+        assert_eq!(droughts(&draws, &[25]).current, None);
+    }
+
+    #[test]
+    fn timeline_lists_every_hit_with_the_run_before_it() {
+        let draws = series(&[1, 5, 2, 3, 5, 5]);
+        let date = |day| NaiveDate::from_ymd_opt(2024, 2, day).unwrap();
+
+        assert_eq!(
+            timeline(&draws, &[5]),
+            vec![
+                DroughtPoint { date: date(2), draws_before: 1, complete: false },
+                DroughtPoint { date: date(5), draws_before: 2, complete: true },
+                DroughtPoint { date: date(6), draws_before: 0, complete: true },
+            ]
+        );
+        assert!(timeline(&draws, &[25]).is_empty());
+    }
+
+    #[test]
+    fn probabilities_of_a_drought_at_least_that_long() {
+        assert_eq!(theoretical_probability(&[5], 0), 1.0);
+        assert!((theoretical_probability(&[5], 2) - 0.96 * 0.96).abs() < 1e-12);
+        // Grupo repetido conta uma vez só
+        assert_eq!(theoretical_probability(&[5, 5, 6, 7, 8, 9], 3), theoretical_probability(&[5, 6, 7, 8, 9], 3));
+        assert!((theoretical_probability(&[1, 2, 3, 4, 5], 1) - 0.8).abs() < 1e-12);
+        // Grupos que não existem não contam
+        assert_eq!(theoretical_probability(&[0, 5, 26], 2), theoretical_probability(&[5], 2));
+        let all: Vec<u32> = (0..=30).collect();
+        assert_eq!(theoretical_probability(&all, 3), 0.0);
+
+        assert_eq!(empirical_probability(&[2, 0, 3, 5], 3), Some(0.5));
+        assert_eq!(empirical_probability(&[2, 0, 3, 5], 6), Some(0.0));
+        assert_eq!(empirical_probability(&[], 3), None);
+
+        // Poucas secas terminadas: fica a teórica
+        assert_eq!(ranking_probability(0.5, &[2, 0, 3, 5], 3), 0.5);
+        // Com bastante, a histórica, contando a atual
+        let completed: Vec<usize> = (0..39).collect();
+        assert_eq!(ranking_probability(0.5, &completed, 30), 10.0 / 40.0);
+        assert_eq!(ranking_probability(0.5, &completed, 100), 1.0 / 40.0);
+
+        assert_eq!(surprise(1.0), 0.0);
+        assert!((surprise(0.001) - 3.0).abs() < 1e-12);
+        assert!(surprise(0.0).is_finite());
+    }
+
+    #[test]
+    fn stats_use_nearest_rank_percentiles() {
+        let stats = drought_stats(&[10, 0, 3, 7, 1, 2, 5, 4, 6, 9, 8, 30]);
+
+        assert_eq!(stats.count, 12);
+        assert_eq!(stats.max, Some(30));
+        assert_eq!(stats.mean, Some(85.0 / 12.0));
+        assert_eq!((stats.p50, stats.p90, stats.p95, stats.p99), (Some(5), Some(10), Some(30), Some(30)));
+
+        assert_eq!(drought_stats(&[]).max, None);
+    }
+}
@@ -0,0 +1,108 @@
+use super::conn::get_connection;
+use super::db::{self, BichoData};
+use super::repo::{RawPayloadInfo, RawPayloadRepo};
+use super::source::RawPage;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+
+pub fn sha256_hex(body: &str) -> String {
+    format!("{:x}", Sha256::digest(body.as_bytes()))
+}
+
+fn compress(body: &str) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(body.as_bytes())?;
+
+    encoder.finish()
+}
+
+fn decompress(compressed: &[u8]) -> std::io::Result<String> {
+    let mut body = String::new();
+    GzDecoder::new(compressed).read_to_string(&mut body)?;
+
+    Ok(body)
+}
+
+// Guarda as respostas da fonte para a loteria. Respostas repetidas não
+// ocupam espaço de novo.
+pub fn store_pages(lottery: &str, pages: &[RawPage]) -> Result<(), String> {
+    let mut conn = get_connection();
+    let tx = conn.transaction().map_err(|err| err.to_string())?;
+    db::ensure_house(&tx, lottery).map_err(|err| err.to_string())?;
+
+    let payloads = RawPayloadRepo::new(&tx);
+    for page in pages {
+        let compressed = compress(&page.body).map_err(|err| err.to_string())?;
+        payloads
+            .insert(lottery, page, &sha256_hex(&page.body), &compressed)
+            .map_err(|err| err.to_string())?;
+    }
+
+    tx.commit().map_err(|err| err.to_string())
+}
+
+pub fn list(lottery: Option<String>) -> Result<Vec<RawPayloadInfo>, String> {
+    let conn = get_connection();
+
+    RawPayloadRepo::new(&conn)
+        .list(lottery.as_deref())
+        .map_err(|err| err.to_string())
+}
+
+// Junta as respostas guardadas da loteria (todas ou só as `ids`) num JSON só,
+// como o do `fetch_draws`. Quando duas trazem o mesmo sorteio, vale a mais nova.
+// Retorna quantas respostas foram usadas e o JSON.
+pub fn merged_payloads(lottery: &str, ids: Option<&[i64]>) -> Result<(usize, String), String> {
+    let conn = get_connection();
+    let payloads = RawPayloadRepo::new(&conn);
+
+    let mut merged: Option<BichoData> = None;
+    let mut used = 0;
+    for info in payloads.list(Some(lottery)).map_err(|err| err.to_string())? {
+        if ids.is_some_and(|ids| !ids.contains(&info.id)) {
+            continue;
+        }
+
+        let compressed = payloads.body(info.id).map_err(|err| err.to_string())?;
+        let body = decompress(&compressed).map_err(|err| err.to_string())?;
+        if sha256_hex(&body) != info.sha256 {
+            return Err(format!("A resposta {} do arquivo está corrompida", info.id));
+        }
+
+        let page: BichoData = serde_json::from_str(&body)
+            .map_err(|err| format!("Erro ao ler a resposta {} ({}): {}", info.id, info.url, err))?;
+        match merged.as_mut() {
+            Some(data) => {
+                data.merge(page);
+            }
+            None => merged = Some(page),
+        }
+        used += 1;
+    }
+
+    match merged {
+        Some(data) => Ok((used, serde_json::to_string(&data).map_err(|err| err.to_string())?)),
+        None => Err(format!("Nenhuma resposta guardada para {}", lottery)),
+    }
+}
+
+#[cfg(test)]
+mod testes {
+    use super::*;
+
+    #[test]
+    fn compressed_body_comes_back_the_same() {
+        let body = r#"{"bicho_lotteries_draws": [], "show_more": false, "status": "ok"}"#.repeat(50);
+        let compressed = compress(&body).unwrap();
+
+        assert!(compressed.len() < body.len());
+        assert_eq!(decompress(&compressed).unwrap(), body);
+        assert_eq!(
+            sha256_hex(""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+}
@@ -1,36 +1,149 @@
+use super::migrations;
+use super::settings::Settings;
 use r2d2::{Pool, PooledConnection};
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::Result;
+use serde::Serialize;
+use std::env;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::Duration;
 
-// Inicializa a conexão com pool
-lazy_static::lazy_static! {
-    static ref POOL: Pool<SqliteConnectionManager> = {
-        let manager = SqliteConnectionManager::file("bicho.db");
-        Pool::new(manager).expect("Falha ao criar o pool de conexões")
-    };
+pub const DB_FILE: &str = "bicho.db";
+
+// Variável de ambiente que troca o caminho do banco
+pub const DB_PATH_ENV: &str = "BICHO_DB_PATH";
+
+// De onde veio o caminho do banco em uso
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DbPathSource {
+    Env,
+    Settings,
+    Default,
+    Workspace,
 }
 
-// Função para retornar a conexão do pool
-pub fn get_connection() -> PooledConnection<SqliteConnectionManager> {
-    POOL.get().expect("Falha ao obter conexão do pool")
+#[derive(Serialize, Debug, Clone)]
+pub struct DbLocation {
+    pub path: PathBuf,
+    pub source: DbPathSource,
+}
+
+struct Database {
+    location: DbLocation,
+    pool: Pool<SqliteConnectionManager>,
+}
+
+// Só existe depois do `open_db`, chamado ao abrir o app
+static DATABASE: RwLock<Option<Database>> = RwLock::new(None);
+
+// Caminho do banco: `BICHO_DB_PATH`, depois `db_path` das configurações e,
+// por fim, `bicho.db` na pasta de dados do app
+pub fn resolve_db_path(app_data_dir: &Path, settings: &Settings) -> DbLocation {
+    choose_db_path(env::var_os(DB_PATH_ENV).map(PathBuf::from), settings, app_data_dir)
 }
 
-// Função para inicializar o banco de dados, caso necessário
-pub fn initialize_db() -> Result<()> {
-    let db_path = "bicho.db";
+fn choose_db_path(env_path: Option<PathBuf>, settings: &Settings, app_data_dir: &Path) -> DbLocation {
+    match (env_path, &settings.db_path) {
+        (Some(path), _) if !path.as_os_str().is_empty() => DbLocation {
+            path,
+            source: DbPathSource::Env,
+        },
+        (_, Some(path)) => DbLocation {
+            path: app_data_dir.join(path),
+            source: DbPathSource::Settings,
+        },
+        _ => DbLocation {
+            path: app_data_dir.join(DB_FILE),
+            source: DbPathSource::Default,
+        },
+    }
+}
+
+fn build_pool(path: &Path) -> Result<Pool<SqliteConnectionManager>, String> {
+    // Várias casas sincronizando ao mesmo tempo disputam a escrita no banco.
+    // As chaves estrangeiras do SQLite ficam desligadas se não forem ativadas.
+    let manager = SqliteConnectionManager::file(path).with_init(|conn| {
+        conn.busy_timeout(Duration::from_secs(60))?;
+        conn.execute_batch("PRAGMA foreign_keys = ON")
+    });
+
+    Pool::new(manager).map_err(|err| format!("Falha ao criar o pool de conexões: {}", err))
+}
+
+// Cria o arquivo do banco, se preciso, e aplica as migrações pendentes
+pub fn prepare_db(path: &Path) -> Result<Pool<SqliteConnectionManager>, String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+    }
+
+    let pool = build_pool(path)?;
+    {
+        let mut conn = pool.get().map_err(|err| err.to_string())?;
+        let version = migrations::migrate(&mut conn)?;
+        println!("Banco de dados {} na versão {} do esquema", path.display(), version);
+    }
+
+    Ok(pool)
+}
 
-    if !db_exists(db_path) {
-        println!("Banco de dados não encontrado. Criando novo banco...");
-        let conn = get_connection();
-    } else {
-        println!("Banco de dados encontrado. Conectando...");
+// Abre o banco em `location` e passa a usá-lo em todas as conexões.
+// O banco anterior só é trocado se o novo abrir sem erros.
+pub fn open_db(location: DbLocation) -> Result<(), String> {
+    // Versões antigas salvavam o banco na pasta de onde o app era aberto
+    if location.source == DbPathSource::Default && !location.path.exists() && Path::new(DB_FILE).exists() {
+        if let Some(dir) = location.path.parent() {
+            fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        println!("Copiando o banco antigo para {}", location.path.display());
+        fs::copy(DB_FILE, &location.path).map_err(|err| err.to_string())?;
     }
 
+    let pool = prepare_db(&location.path)?;
+    *DATABASE.write().expect("Falha ao travar o banco") = Some(Database { location, pool });
+
     Ok(())
 }
 
-// Verifica se o banco de dados já existe
-fn db_exists(path: &str) -> bool {
-    fs::metadata(path).is_ok()
+// Caminho do banco em uso, se ele já foi aberto
+pub fn db_location() -> Option<DbLocation> {
+    let database = DATABASE.read().expect("Falha ao travar o banco");
+
+    database.as_ref().map(|database| database.location.clone())
+}
+
+// Função para retornar a conexão do pool
+pub fn get_connection() -> PooledConnection<SqliteConnectionManager> {
+    let database = DATABASE.read().expect("Falha ao travar o banco");
+
+    database
+        .as_ref()
+        .expect("O banco de dados ainda não foi aberto")
+        .pool
+        .get()
+        .expect("Falha ao obter conexão do pool")
+}
+
+#[cfg(test)]
+mod testes {
+    use super::*;
+
+    #[test]
+    fn env_wins_over_settings_and_default() {
+        let data_dir = PathBuf::from("/dados");
+        let settings = Settings {
+            db_path: Some(PathBuf::from("meu.db")),
+            ..Settings::default()
+        };
+
+        let location = choose_db_path(Some(PathBuf::from("/tmp/env.db")), &settings, &data_dir);
+        assert_eq!((location.path, location.source), (PathBuf::from("/tmp/env.db"), DbPathSource::Env));
+
+        let location = choose_db_path(None, &settings, &data_dir);
+        assert_eq!((location.path, location.source), (data_dir.join("meu.db"), DbPathSource::Settings));
+
+        let location = choose_db_path(Some(PathBuf::new()), &Settings::default(), &data_dir);
+        assert_eq!((location.path, location.source), (data_dir.join(DB_FILE), DbPathSource::Default));
+    }
 }
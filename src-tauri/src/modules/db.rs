@@ -1,22 +1,42 @@
+use super::analysis::{self, DrawPoint, DroughtPoint, DroughtStats, History};
 use super::conn::get_connection; // Usamos a função para obter uma conexão do pool
+use super::progress::{Phase, ProgressStep};
+use super::repo::{
+    self, Cutoff, DrawRepo, DrawRevision, FlaggedDraw, GroupRepo, NewDraw, QuarantineRepo,
+    QuarantinedDraw, RevisionRepo, UpsertOutcome,
+};
+use super::web::House;
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Utc};
 use regex::Regex;
-use rusqlite::Row;
+use rusqlite::{params, Connection, Row};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
-use std::fmt::format;
 use std::fs::File;
 use std::io::BufWriter;
 use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 #[derive(Serialize)]
 pub struct DatabaseInfo {
     total_rows: i32,
-    date: Option<i64>,
+    date: Option<i64>,           // Data do sorteio mais recente salvo
+    last_synced_at: Option<i64>, // Última sincronização bem sucedida
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SyncState {
+    pub house: String,
+    pub last_draw_date: Option<i64>,
+    pub last_draw_hour: Option<String>,
+    pub last_synced_at: Option<i64>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Lottery {
     title: Option<String>, // Torna o campo `title` opcional
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>, // Campos que não usamos, guardados como vieram
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -27,21 +47,56 @@ pub struct BichoDraw {
     hour: Option<String>,     // Pode ser `null`
     group: Option<String>,    // Pode ser `null`
     date: Option<String>,     // Pode ser `null`
+    // Campos que não usamos. Ficam no JSON da quarentena e do arquivo para
+    // que uma correção do parser ainda possa aproveitá-los.
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct BichoData {
     bicho_lotteries_draws: Vec<Vec<BichoDraw>>, // A estrutura permanece a mesma
-    show_more: bool,
+    pub show_more: bool,
     status: String,
 }
 
+// Resultado de um `save_bicho_data`
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct SaveReport {
+    pub inserted: usize,
+    pub updated: usize,    // Já salvas, mas com outro milhar ou grupo
+    pub duplicates: usize, // Já salvas iguais
+    pub skipped: usize,    // Linhas inválidas
+    pub flagged: usize,    // Linhas gravadas com o grupo do site divergente
+    pub rejected: usize,   // Linhas mandadas para a quarentena no modo estrito
+    pub revisions: Vec<DrawRevision>, // Correções feitas pelo site nos sorteios já salvos
+}
+
+// Modo estrito da gravação, vindo das configurações
+static STRICT_INGESTION: AtomicBool = AtomicBool::new(false);
+
+pub fn set_strict_ingestion(strict: bool) {
+    STRICT_INGESTION.store(strict, Ordering::Relaxed);
+}
+
+pub fn strict_ingestion() -> bool {
+    STRICT_INGESTION.load(Ordering::Relaxed)
+}
+
+// Sorteios com o grupo divergente de uma casa
+#[derive(Serialize, Debug)]
+pub struct HouseQuality {
+    pub lottery: String,
+    pub house: String,
+    pub flagged: Vec<FlaggedDraw>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct BichoGroup {
-    id: Option<u32>,
-    hour: String,
-    place: u32,
-    group: Vec<u32>,
+    pub id: Option<u32>,
+    pub hour: String,
+    pub place: u32,
+    pub group: Vec<u32>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -50,36 +105,129 @@ pub struct LossSequence {
     pub group: String,
     pub place: u32,
     pub loss_sequence: i32,
+    // Chance de uma seca ao menos tão longa quanto a atual pela taxa de
+    // acerto dos grupos (k/25 por sorteio)
+    pub theoretical_probability: f64,
+    // A mesma chance pelas secas já terminadas na casa. `None` sem nenhuma.
+    pub empirical_probability: Option<f64>,
+    // Raridade da seca atual, pela qual a tela ordena as recomendações. Sai da
+    // chance histórica quando a casa tem secas terminadas suficientes, senão
+    // da teórica (`analysis::ranking_probability`).
+    pub surprise: f64,
+}
+
+// Secas já terminadas de um grupo cadastrado, em todo o histórico salvo
+#[derive(Serialize, Debug)]
+pub struct GroupDroughtStats {
+    pub hour: String,
+    pub place: u32,
+    pub group: String,
+    #[serde(flatten)]
+    pub stats: DroughtStats,
+}
+
+impl BichoData {
+    pub fn total_draws(&self) -> usize {
+        self.bicho_lotteries_draws.iter().map(|g| g.len()).sum()
+    }
+
+    // Data do sorteio mais antigo da página
+    pub fn oldest_date(&self) -> Option<NaiveDate> {
+        self.bicho_lotteries_draws
+            .iter()
+            .flatten()
+            .filter_map(|draw| draw.date.as_deref().and_then(parse_draw_date))
+            .min()
+    }
+
+    // Junta outra página, ignorando sorteios (data, hora, prêmio) já presentes.
+    // Retorna quantos sorteios novos entraram.
+    pub fn merge(&mut self, other: BichoData) -> usize {
+        let mut added = 0;
+        let mut seen: HashSet<(Option<String>, Option<String>, Option<String>)> = self
+            .bicho_lotteries_draws
+            .iter()
+            .flatten()
+            .map(|draw| (draw.date.clone(), draw.hour.clone(), draw.place.clone()))
+            .collect();
+
+        for mut draw_group in other.bicho_lotteries_draws {
+            draw_group.retain(|draw| {
+                seen.insert((draw.date.clone(), draw.hour.clone(), draw.place.clone()))
+            });
+            if !draw_group.is_empty() {
+                added += draw_group.len();
+                self.bicho_lotteries_draws.push(draw_group);
+            }
+        }
+
+        self.show_more = other.show_more;
+        added
+    }
+
+    // Mantém apenas os sorteios com data entre `from` e `to` (inclusive)
+    pub fn retain_between(&mut self, from: NaiveDate, to: NaiveDate) {
+        for draw_group in self.bicho_lotteries_draws.iter_mut() {
+            draw_group.retain(|draw| {
+                match draw.date.as_deref().and_then(parse_draw_date) {
+                    Some(date) => date >= from && date <= to,
+                    None => false,
+                }
+            });
+        }
+        self.bicho_lotteries_draws.retain(|draw_group| !draw_group.is_empty());
+    }
+}
+
+// Converte a data no formato do site (dd/mm/aaaa)
+pub fn parse_draw_date(date_str: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date_str, "%d/%m/%Y").ok()
 }
 
-fn format_database_name(house_name: &str) -> String {
+// Nome da tabela de sorteios usada quando cada casa tinha a sua, a partir do
+// id da loteria. Caracteres fora de [A-Za-z0-9] viravam `_<hex>_`.
+fn house_table_name(lottery: &str) -> String {
+    let mut table_name = String::from("house_");
+    for c in lottery.chars() {
+        if c.is_ascii_alphanumeric() {
+            table_name.push(c);
+        } else {
+            table_name.push_str(&format!("_{:x}_", c as u32));
+        }
+    }
+
+    table_name
+}
+
+fn group_table_name(lottery: &str) -> String {
+    String::from("group_") + &house_table_name(lottery)
+}
+
+// Nome usado antes das tabelas serem identificadas pelo id da loteria
+fn legacy_table_name(house_name: &str) -> String {
     let re = Regex::new(r"[-\s]").unwrap();
-    let database_name = re.replace_all(&house_name, "_");
+    let database_name = re.replace_all(house_name, "_");
 
     database_name.to_string() // Converte de volta para String
 }
 
-pub fn get_table_info(house_name: String) -> Result<DatabaseInfo, ()> {
-    let table_name = format_database_name(&house_name);
+pub fn get_table_info(lottery: String) -> Result<DatabaseInfo, ()> {
     let conn = get_connection();
 
-    // Query para pegar a contagem e o `updated_at` mais recente
-    let query = format!("SELECT count(*), MAX(updated_at) FROM {}", table_name);
-
-    // Captura os resultados (contagem e timestamp)
-    let result: rusqlite::Result<DatabaseInfo> = conn.query_row(&query, [], |row| {
-        Ok(DatabaseInfo {
-            total_rows: row.get(0)?,
-            date: row.get(1)?,
-        })
-    });
+    // Query para pegar a contagem de sorteios
+    let result = DrawRepo::new(&conn).count(&lottery);
 
     match result {
-        Ok(info) => Ok(info),
-        Err(rusqlite::Error::SqliteFailure(_, Some(msg))) if msg.contains("no such table") => {
-            println!("Erro: A tabela '{}' não existe.", table_name);
-            create_house_table_in_not_exists(&table_name).expect("Erro ao criar a tabela");
-            get_table_info(house_name)
+        Ok(total_rows) => {
+            let state = get_sync_state(&lottery).map_err(|err| {
+                println!("Erro ao ler o estado de sincronização: {:?}", err);
+            })?;
+
+            Ok(DatabaseInfo {
+                total_rows,
+                date: state.last_draw_date,
+                last_synced_at: state.last_synced_at,
+            })
         }
         Err(err) => {
             println!("Erro inesperado: {:?}", err);
@@ -88,270 +236,559 @@ pub fn get_table_info(house_name: String) -> Result<DatabaseInfo, ()> {
     }
 }
 
-fn create_house_table_in_not_exists(table_name: &str) -> Result<(), rusqlite::Error> {
+// Garante que a loteria exista em `houses` para os sorteios e grupos poderem
+// referenciá-la. Se ela ainda não veio na lista do site, entra como desaparecida.
+pub fn ensure_house(conn: &Connection, lottery: &str) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "INSERT OR IGNORE INTO houses (value, name, position, first_seen, last_seen, disappeared_at)
+         SELECT ?1, ?1, count(*), strftime('%s', 'now'), 0, strftime('%s', 'now') FROM houses",
+        [lottery],
+    )?;
+
+    Ok(())
+}
+
+// Salva a lista de casas vinda do site. Casas que não vieram nesta lista
+// são marcadas como desaparecidas.
+pub fn save_houses(houses: &[House]) -> Result<(), rusqlite::Error> {
+    let mut conn = get_connection();
+
+    let now = Utc::now().timestamp();
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT INTO houses (value, name, position, first_seen, last_seen, disappeared_at)
+             VALUES (?1, ?2, ?3, ?4, ?4, NULL)
+             ON CONFLICT (value) DO UPDATE SET
+                name = excluded.name,
+                position = excluded.position,
+                last_seen = excluded.last_seen,
+                disappeared_at = NULL",
+        )?;
+        for (position, house) in houses.iter().enumerate() {
+            stmt.execute(params![house.value, house.name, position as i64, now])?;
+        }
+    }
+    tx.execute(
+        "UPDATE houses SET disappeared_at = ?1 WHERE last_seen < ?1 AND disappeared_at IS NULL",
+        [now],
+    )?;
+    tx.commit()?;
+
+    Ok(())
+}
+
+pub fn get_saved_houses() -> Result<Vec<House>, rusqlite::Error> {
     let conn = get_connection();
 
-    let query = format!(
-        "CREATE TABLE IF NOT EXISTS {} (
-            id INTEGER PRIMARY KEY,
-            place INTEGER NOT NULL,
-            date INTEGER NOT NULL,
-            hour TEXT NOT NULL,
-            milhar INTEGER NOT NULL,
-            \"group\" INTEGER NOT NULL,
-            updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
-            UNIQUE (place, date, hour)
-        )",
-        table_name
-    );
-
-    conn.execute(&query, [])?;
+    let mut stmt = conn.prepare(
+        "SELECT name, value, disappeared_at IS NOT NULL FROM houses
+         ORDER BY disappeared_at IS NOT NULL, position",
+    )?;
+    let houses = stmt
+        .query_map([], |row| {
+            Ok(House {
+                name: row.get(0)?,
+                value: row.get(1)?,
+                disappeared: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(houses)
+}
+
+// Move os dados das tabelas antigas, uma por casa, para `draws` e `groups`.
+// Elas eram nomeadas primeiro pelo nome da casa e depois pelo id da loteria,
+// então depende da lista de casas salva para saber a qual loteria pertencem.
+pub fn adopt_legacy_tables() -> Result<(), rusqlite::Error> {
+    let houses = get_saved_houses()?;
+    let mut conn = get_connection();
+
+    for house in houses {
+        let tx = conn.transaction()?;
+        if adopt_house_tables(&tx, &house)? {
+            tx.commit()?;
+        }
+    }
 
     Ok(())
 }
 
-fn create_group_table_in_not_exists(table_name: &str) -> Result<(), rusqlite::Error> {
+// Adota as tabelas antigas de uma casa. Retorna se havia alguma.
+// Os milhares salvos como número voltam com os zeros e o grupo é calculado
+// como na migração 3, guardando o antigo quando diverge (o 999 é só ausente).
+fn adopt_house_tables(tx: &Connection, house: &House) -> Result<bool, rusqlite::Error> {
+    let legacy = legacy_table_name(&house.name);
+    let draw_tables = [legacy.clone(), house_table_name(&house.value)];
+    let group_tables = [String::from("group_") + &legacy, group_table_name(&house.value)];
+
+    let mut found = false;
+    for table in draw_tables.iter() {
+        let table = match repo::checked_table(tx, table)? {
+            Some(table) => table,
+            None => continue,
+        };
+
+        println!("Migrando a tabela '{}' para os sorteios da loteria '{}'", table, house.value);
+        tx.execute(
+            &format!(
+                "INSERT OR IGNORE INTO draws (house_id, place, date, hour, milhar, dezena, centena, \"group\",
+                    scraped_group, updated_at)
+                 SELECT ?1, place, date, hour, milhar,
+                    CASE WHEN milhar = '999' THEN '' ELSE substr(milhar, -2) END,
+                    CASE WHEN milhar = '999' THEN '' ELSE substr(milhar, -3) END,
+                    coalesce(derived, \"group\"),
+                    CASE WHEN derived IS NOT NULL AND derived IS NOT \"group\" THEN NULLIF(\"group\", 999) END,
+                    updated_at
+                 FROM (
+                    SELECT *,
+                        CASE WHEN length(milhar) >= 2 AND milhar NOT GLOB '*[^0-9]*' AND milhar <> '999' THEN
+                            CASE WHEN CAST(substr(milhar, -2) AS INTEGER) = 0 THEN 25
+                            ELSE (CAST(substr(milhar, -2) AS INTEGER) + 3) / 4 END
+                        END AS derived
+                    FROM (
+                        SELECT place, date, hour, \"group\", updated_at,
+                            CASE WHEN typeof(milhar) = 'integer' AND (milhar <> 999 OR \"group\" = 25)
+                                THEN printf('%04d', milhar) ELSE CAST(milhar AS TEXT) END AS milhar
+                        FROM {}
+                    )
+                 )",
+                table
+            ),
+            [&house.value],
+        )?;
+        tx.execute(&format!("DROP TABLE {}", table), [])?;
+        found = true;
+    }
+    for table in group_tables.iter() {
+        let table = match repo::checked_table(tx, table)? {
+            Some(table) => table,
+            None => continue,
+        };
+
+        println!("Migrando a tabela '{}' para os grupos da loteria '{}'", table, house.value);
+        tx.execute(
+            &format!(
+                "INSERT OR IGNORE INTO groups (house_id, hour, place, \"group\", updated_at)
+                 SELECT ?1, hour, place, \"group\", updated_at FROM {}",
+                table
+            ),
+            [&house.value],
+        )?;
+        tx.execute(&format!("DROP TABLE {}", table), [])?;
+        found = true;
+    }
+    if !found {
+        return Ok(false);
+    }
+
+    // O estado de sincronização também era salvo pelo nome da casa
+    tx.execute(
+        "UPDATE OR IGNORE sync_state SET house = ?1 WHERE house = ?2",
+        params![house.value, house.name],
+    )?;
+    if house.name != house.value {
+        tx.execute("DELETE FROM sync_state WHERE house = ?1", [&house.name])?;
+    }
+    refresh_sync_state(tx, &house.value, false)?;
+
+    Ok(true)
+}
+
+// Momento da última lista de casas baixada do site
+pub fn houses_updated_at() -> Result<Option<i64>, rusqlite::Error> {
     let conn = get_connection();
 
-    let query = format!(
-        "CREATE TABLE IF NOT EXISTS {} (
-            id INTEGER PRIMARY KEY,
-            hour TEXT NOT NULL,
-            place INTEGER NOT NULL,
-            \"group\" TEXT NOT NULL,
-            updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
-            UNIQUE (hour, place)
-        )",
-        table_name
-    );
+    conn.query_row("SELECT MAX(last_seen) FROM houses", [], |row| row.get(0))
+}
 
-    conn.execute(&query, [])?;
+// Recalcula o sorteio mais recente salvo da casa. Com `synced`, marca
+// também o momento da sincronização.
+fn refresh_sync_state(
+    conn: &Connection,
+    lottery: &str,
+    synced: bool,
+) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "INSERT INTO sync_state (house, last_draw_date, last_draw_hour, last_synced_at)
+         SELECT ?1, date, hour, CASE WHEN ?2 THEN strftime('%s', 'now') END
+         FROM (SELECT NULL)
+         LEFT JOIN (
+            SELECT date, hour FROM draws WHERE house_id = ?1 ORDER BY date DESC, hour DESC LIMIT 1
+         ) ON 1
+         WHERE true
+         ON CONFLICT (house) DO UPDATE SET
+            last_draw_date = excluded.last_draw_date,
+            last_draw_hour = excluded.last_draw_hour,
+            last_synced_at = COALESCE(excluded.last_synced_at, sync_state.last_synced_at)",
+        params![lottery, synced],
+    )?;
 
     Ok(())
 }
 
+pub fn get_sync_state(lottery: &str) -> Result<SyncState, rusqlite::Error> {
+    let conn = get_connection();
+
+    let query = "SELECT house, last_draw_date, last_draw_hour, last_synced_at FROM sync_state WHERE house = ?1";
+    let read_state = |row: &Row| {
+        Ok(SyncState {
+            house: row.get(0)?,
+            last_draw_date: row.get(1)?,
+            last_draw_hour: row.get(2)?,
+            last_synced_at: row.get(3)?,
+        })
+    };
+
+    match conn.query_row(query, [lottery], read_state) {
+        Err(rusqlite::Error::QueryReturnedNoRows) => {
+            // Bancos antigos não têm o estado salvo, então ele vem dos próprios sorteios
+            refresh_sync_state(&conn, lottery, false)?;
+            conn.query_row(query, [lottery], read_state)
+        }
+        result => result,
+    }
+}
+
 pub fn save_bicho_data<F>(
-    house_name: String,
+    lottery: String,
     bicho_data: &str,
+    synced: bool,             // Veio do site agora, então marca o momento da sincronização
+    cancel: &AtomicBool,      // Quando marcado, desfaz tudo o que foi inserido
     mut progress_callback: F, // Callback que recebe o progresso
-) -> Result<(), Box<dyn std::error::Error>>
+) -> Result<SaveReport, Box<dyn std::error::Error>>
 where
-    F: FnMut(f32), // O callback recebe um f32 que representa o progresso
+    F: FnMut(ProgressStep), // O callback recebe a fase e quanto dela já foi feito
 {
-    let table_name = format_database_name(&house_name);
-    match create_house_table_in_not_exists(&table_name) {
-        Ok(_) => (),
-        Err(err) => return Err(Box::new(err)),
+    let step = |phase: Phase, processed: usize, total: usize, rejected: usize| ProgressStep {
+        phase,
+        processed,
+        total,
+        rejected,
     };
 
-    let deserialized: BichoData = serde_json::from_str(&bicho_data)?;
-    let total_draws = deserialized
-        .bicho_lotteries_draws
-        .iter()
-        .map(|g| g.len())
-        .sum::<usize>(); // Número total de inserções
+    progress_callback(step(Phase::Parse, 0, 1, 0));
+    let deserialized: BichoData = serde_json::from_str(bicho_data)?;
+    progress_callback(step(Phase::Parse, 1, 1, 0));
+
+    let total_draws = deserialized.total_draws(); // Número total de inserções
     let mut conn = get_connection(); // Obtém conexão
     let tx = conn.transaction()?; // Inicia uma transação para as inserções
+    ensure_house(&tx, &lottery)?;
+    let draws = DrawRepo::new(&tx);
+    let quarantine = QuarantineRepo::new(&tx);
+    let strict = strict_ingestion();
+    let revisions = RevisionRepo::new(&tx);
+    let last_revision = revisions.last_id()?;
+
+    let mut report = SaveReport::default();
+
+    for (processed, draw) in deserialized.bicho_lotteries_draws.iter().flatten().enumerate() {
+        if cancel.load(Ordering::Relaxed) {
+            // Sair sem o commit faz a transação ser desfeita
+            return Err("Gravação cancelada".into());
+        }
 
-    let mut total_inserts = 0;
-    let batch_size = 100; // Defina o tamanho do lote
-    let mut params: Vec<String> = Vec::new();
-
-    for draw_group in deserialized.bicho_lotteries_draws.iter() {
-        for draw in draw_group.iter() {
-            let position: i32 = match draw.place.as_ref().unwrap_or(&"999".to_string()).parse() {
-                Ok(place) => place,
-                Err(_) => continue,
-            };
-
-            let formatted_date = if let Some(date_str) = draw.date.as_ref() {
-                let parts: Vec<&str> = date_str.split('/').collect();
-                if parts.len() == 3 {
-                    format!("{}-{}-{}", parts[2], parts[1], parts[0])
-                } else {
-                    "1970-01-01".to_string()
-                }
-            } else {
-                "1970-01-01".to_string()
-            };
-
-            let insert_query = format!(
-                "INSERT INTO {} (place, date, hour, milhar, \"group\", updated_at) 
-                 VALUES ({}, strftime('%s', '{}'), '{}', '{}', '{}', strftime('%s', 'now'))",
-                table_name,
-                position,
-                formatted_date,
-                draw.hour.as_ref().unwrap_or(&"999".to_string()),
-                draw.thousand.as_ref().unwrap_or(&"999".to_string()),
-                draw.group.as_ref().unwrap_or(&"999".to_string())
-            );
+        // Chama o callback para atualizar o progresso
+        progress_callback(step(Phase::Insert, processed, total_draws, report.skipped + report.rejected));
 
-            // Testa se a query é válida
-            match tx.execute(&insert_query, []) {
-                Ok(_) => {
-                    total_inserts += 1;
-
-                    // Adiciona a query ao batch
-                    params.push(insert_query);
-
-                    // Quando atingir o batch size, executa as queries acumuladas
-                    if total_inserts % batch_size == 0 {
-                        // Executa cada query individualmente dentro do batch
-                        for query in params.iter() {
-                            if let Err(_) = tx.execute(query, []) {}
-                        }
-                        params.clear(); // Limpa o lote após a execução
-                    }
-                }
-                Err(err) => {
-                    println!("Erro ao validar linha: {:?}", err);
-                    continue;
-                }
+        let row = match prepare_draw(draw, strict) {
+            Ok(row) => row,
+            Err(reason) if strict => {
+                quarantine.insert(&lottery, &reason, &serde_json::to_string(draw)?)?;
+                report.rejected += 1;
+                continue;
+            }
+            Err(_) => {
+                report.skipped += 1;
+                continue;
             }
+        };
 
-            // Chama o callback para atualizar o progresso
-            let progress = (total_inserts as f32 / total_draws as f32) * 100.0;
-            progress_callback(progress);
+        match draws.upsert(&lottery, &row) {
+            Ok(UpsertOutcome::Unchanged) => {
+                report.duplicates += 1;
+                continue;
+            }
+            Ok(UpsertOutcome::Inserted) => report.inserted += 1,
+            Ok(UpsertOutcome::Updated) => report.updated += 1,
+            Err(err) => {
+                println!("Erro ao gravar linha: {:?}", err);
+                report.skipped += 1;
+                continue;
+            }
+        }
+        if row.scraped_group.is_some() {
+            report.flagged += 1;
         }
     }
+    progress_callback(step(Phase::Insert, total_draws, total_draws, report.skipped + report.rejected));
+    report.revisions = revisions.list(Some(&lottery), last_revision)?;
 
-    // Executa as queries restantes, se houver
-    if !params.is_empty() {
-        for query in params.iter() {
-            if let Err(err) = tx.execute(query, []) {
-                println!("Erro ao inserir linha: {:?}", err);
+    // Atualiza o estado de sincronização junto com os sorteios
+    progress_callback(step(Phase::Analyze, 0, 1, 0));
+    refresh_sync_state(&tx, &lottery, synced)?;
+
+    // Finaliza a transação
+    tx.commit()?; // Confirma as inserções válidas
+
+    // Chama o callback com o fim da gravação
+    progress_callback(step(Phase::Analyze, 1, 1, 0));
+
+    Ok(report)
+}
+
+// Converte o sorteio do site numa linha de `draws`. Fora do modo estrito,
+// campos ausentes viram "999" e datas inválidas 1970-01-01, como sempre foi.
+// No modo estrito, eles rejeitam a linha. Retorna o motivo da rejeição.
+fn prepare_draw(draw: &BichoDraw, strict: bool) -> Result<NewDraw, String> {
+    let mut problems: Vec<&str> = Vec::new();
+    if strict {
+        if draw.place.is_none() {
+            problems.push("prêmio ausente");
+        }
+        if draw.hour.is_none() {
+            problems.push("horário ausente");
+        }
+        if draw.thousand.is_none() {
+            problems.push("milhar ausente");
+        }
+        if draw.group.is_none() && draw.thousand.as_deref().and_then(repo::group_of).is_none() {
+            problems.push("grupo ausente");
+        }
+        if draw.date.as_deref().and_then(parse_draw_date).is_none() {
+            problems.push("data ausente ou inválida");
+        }
+    }
+
+    let position: i32 = match draw.place.as_ref().unwrap_or(&"999".to_string()).parse() {
+        Ok(place) => place,
+        Err(_) => {
+            problems.push("prêmio inválido");
+            0
+        }
+    };
+    if !problems.is_empty() {
+        return Err(problems.join(", "));
+    }
+
+    let formatted_date = if let Some(date_str) = draw.date.as_ref() {
+        let parts: Vec<&str> = date_str.split('/').collect();
+        if parts.len() == 3 {
+            format!("{}-{}-{}", parts[2], parts[1], parts[0])
+        } else {
+            "1970-01-01".to_string()
+        }
+    } else {
+        "1970-01-01".to_string()
+    };
+
+    let (group, scraped_group) = resolve_group(draw.thousand.as_deref(), draw.group.as_deref());
+
+    Ok(NewDraw {
+        place: position,
+        date: formatted_date,
+        hour: draw.hour.clone().unwrap_or("999".to_string()),
+        // O marcador "999" de milhar ausente fica sem dezena e centena
+        dezena: draw.thousand.as_deref().map(repo::dezena_of).unwrap_or_default(),
+        centena: draw.thousand.as_deref().map(repo::centena_of).unwrap_or_default(),
+        milhar: draw.thousand.clone().unwrap_or("999".to_string()),
+        group,
+        scraped_group,
+    })
+}
+
+// Resultado de um `reingest_quarantine`
+#[derive(Serialize, Debug, Default)]
+pub struct ReingestReport {
+    pub inserted: usize,
+    pub updated: usize,
+    pub duplicated: usize, // Já estavam salvos, saem da quarentena do mesmo jeito
+    pub rejected: usize,   // Continuam na quarentena
+}
+
+// Histórico de correções dos sorteios. Sem `lottery`, de todas as casas.
+pub fn get_draw_revisions(lottery: Option<String>) -> Result<Vec<DrawRevision>, String> {
+    let conn = get_connection();
+
+    RevisionRepo::new(&conn)
+        .list(lottery.as_deref(), 0)
+        .map_err(|err| err.to_string())
+}
+
+pub fn list_quarantine(lottery: Option<String>) -> Result<Vec<QuarantinedDraw>, String> {
+    let conn = get_connection();
+
+    QuarantineRepo::new(&conn)
+        .list(lottery.as_deref())
+        .map_err(|err| err.to_string())
+}
+
+// Tenta gravar de novo os sorteios da quarentena da loteria (todos ou só os
+// `ids`). Os que passarem saem da quarentena; os outros ficam com o novo motivo.
+pub fn reingest_quarantine(
+    lottery: String,
+    ids: Option<Vec<i64>>,
+    strict: bool,
+) -> Result<ReingestReport, String> {
+    let mut conn = get_connection();
+    let tx = conn.transaction().map_err(|err| err.to_string())?;
+    let draws = DrawRepo::new(&tx);
+    let quarantine = QuarantineRepo::new(&tx);
+
+    let rows = quarantine.list(Some(&lottery)).map_err(|err| err.to_string())?;
+    let mut report = ReingestReport::default();
+    for row in rows {
+        if ids.as_ref().is_some_and(|ids| !ids.contains(&row.id)) {
+            continue;
+        }
+
+        let draw: BichoDraw = serde_json::from_str(&row.raw).map_err(|err| err.to_string())?;
+        match prepare_draw(&draw, strict) {
+            Ok(new_draw) => {
+                match draws.upsert(&lottery, &new_draw).map_err(|err| err.to_string())? {
+                    UpsertOutcome::Inserted => report.inserted += 1,
+                    UpsertOutcome::Updated => report.updated += 1,
+                    UpsertOutcome::Unchanged => report.duplicated += 1,
+                }
+                quarantine.delete(row.id).map_err(|err| err.to_string())?;
+            }
+            Err(reason) => {
+                quarantine
+                    .insert(&lottery, &reason, &row.raw)
+                    .map_err(|err| err.to_string())?;
+                report.rejected += 1;
             }
         }
     }
 
-    // Finaliza a transação
-    tx.commit()?; // Confirma as inserções válidas
+    refresh_sync_state(&tx, &lottery, false).map_err(|err| err.to_string())?;
+    tx.commit().map_err(|err| err.to_string())?;
+
+    Ok(report)
+}
 
-    // Chama o callback com 100% de progresso ao finalizar
-    progress_callback(100.0);
+// Grupo a salvar e, se divergir dele, o grupo que veio do site. Sem milhar
+// válido, confia no site.
+fn resolve_group(thousand: Option<&str>, scraped: Option<&str>) -> (String, Option<String>) {
+    match (thousand.and_then(repo::group_of), scraped) {
+        (Some(derived), Some(scraped)) if scraped.trim().parse::<u32>().ok() == Some(derived) => {
+            (derived.to_string(), None)
+        }
+        (Some(derived), Some(scraped)) => (derived.to_string(), Some(scraped.to_string())),
+        (Some(derived), None) => (derived.to_string(), None),
+        (None, scraped) => (scraped.unwrap_or("999").to_string(), None),
+    }
+}
 
-    Ok(())
+// Sorteios com o grupo do site divergente, por casa. Sem `lottery`, de todas.
+pub fn data_quality_report(lottery: Option<String>) -> Result<Vec<HouseQuality>, String> {
+    let conn = get_connection();
+    let flagged = DrawRepo::new(&conn)
+        .flagged(lottery.as_deref())
+        .map_err(|err| err.to_string())?;
+    let names: HashMap<String, String> = get_saved_houses()
+        .map_err(|err| err.to_string())?
+        .into_iter()
+        .map(|house| (house.value, house.name))
+        .collect();
+
+    // As linhas já vêm ordenadas por casa
+    let mut report: Vec<HouseQuality> = Vec::new();
+    for draw in flagged {
+        match report.last_mut() {
+            Some(house) if house.lottery == draw.house_id => house.flagged.push(draw),
+            _ => report.push(HouseQuality {
+                house: names.get(&draw.house_id).cloned().unwrap_or(draw.house_id.clone()),
+                lottery: draw.house_id.clone(),
+                flagged: vec![draw],
+            }),
+        }
+    }
+
+    Ok(report)
 }
 
 pub fn export_table_to_csv(
-    house_name: String,
+    lottery: String,
     file_path: &str,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let conn = get_connection();
-    let table_name = format_database_name(&house_name);
-
-    // Obtém os nomes das colunas
-    let stmt = conn.prepare(&format!("SELECT * FROM {} LIMIT 1", table_name))?;
-    let column_names: Vec<String> = stmt
-        .column_names()
-        .into_iter()
-        .map(|s| s.to_string())
-        .collect();
-    drop(stmt); // Libera o stmt após obter os nomes das colunas
+    let draws = DrawRepo::new(&conn).all(&lottery)?;
 
     let file = File::create(file_path)?;
     let mut writer = BufWriter::new(file);
 
     // Escreve os nomes das colunas no CSV
-    writeln!(writer, "{}", column_names.join(","))?;
-
-    let mut stmt = conn.prepare(&format!("SELECT * FROM {}", table_name))?;
-    let mut rows = stmt.query([])?;
-
-    while let Some(row) = rows.next()? {
-        let mut values = Vec::new();
-        for idx in 0..column_names.len() {
-            // Tenta converter para String e trata diferentes tipos
-            let value: String = match row.get_ref(idx)? {
-                rusqlite::types::ValueRef::Integer(val) => val.to_string(),
-                rusqlite::types::ValueRef::Real(val) => val.to_string(),
-                rusqlite::types::ValueRef::Text(val) => String::from_utf8_lossy(val).to_string(),
-                rusqlite::types::ValueRef::Null => "".to_string(),
-                _ => "UNSUPPORTED_TYPE".to_string(),
-            };
-            values.push(value);
-        }
-
-        writeln!(writer, "{}", values.join(","))?;
+    writeln!(writer, "id,place,date,hour,milhar,dezena,centena,group,scraped_group,updated_at")?;
+
+    for draw in draws {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},{}",
+            draw.id,
+            draw.place,
+            draw.date,
+            draw.hour,
+            draw.milhar,
+            draw.dezena,
+            draw.centena,
+            draw.group,
+            draw.scraped_group.unwrap_or_default(),
+            draw.updated_at
+        )?;
     }
 
     Ok(())
 }
 
-pub fn get_groups(house_name: String) -> Result<Vec<BichoGroup>, Box<dyn Error>> {
+pub fn get_groups(lottery: String) -> Result<Vec<BichoGroup>, Box<dyn Error>> {
     let conn = get_connection();
-    let table_name = String::from("group_") + &format_database_name(&house_name);
-
-    create_group_table_in_not_exists(&table_name)?;
-
-    let mut stmt = conn.prepare(&format!(
-        "SELECT id, hour, place, \"group\" FROM {}",
-        table_name
-    ))?;
 
     // Mapeia as linhas do resultado para a estrutura BichoGroup
-    let groups = stmt
-        .query_map([], |row| parse_row_to_bicho_group(row))?
-        .collect::<Result<Vec<_>, _>>()?;
+    let groups = GroupRepo::new(&conn).list(&lottery)?;
 
     Ok(groups)
 }
 
-pub fn add_group(house_name: String, data: BichoGroup) -> Result<(), String> {
-    let conn = get_connection();
-    let table_name = String::from("group_") + &format_database_name(&house_name);
-    let joined_str = data
-        .group
-        .iter()
-        .map(|num| num.to_string())
-        .collect::<Vec<String>>()
-        .join(", ");
-    let query = format!( "INSERT INTO {} (hour, place, \"group\", updated_at) VALUES ('{}', '{}', '{}', strftime('%s', 'now'))", table_name, data.hour, data.place, joined_str );
-    if let Err(err) = conn.execute(&query, []) {
-        if err.to_string().contains("UNIQUE constraint failed") {
-            return Err(format!("o grupo das {} horas do {}º premio já está cadastrado", data.hour, data.place));
-        }
-        return Err(err.to_string());
+// Mensagem para quando o grupo do horário e prêmio já existe
+fn group_error(data: &BichoGroup, err: rusqlite::Error) -> String {
+    if err.to_string().contains("UNIQUE constraint failed") {
+        return format!("o grupo das {} horas do {}º premio já está cadastrado", data.hour, data.place);
     }
+
+    err.to_string()
+}
+
+pub fn add_group(lottery: String, data: BichoGroup) -> Result<(), String> {
+    let conn = get_connection();
+
+    ensure_house(&conn, &lottery)
+        .and_then(|_| GroupRepo::new(&conn).insert(&lottery, &data))
+        .map_err(|err| group_error(&data, err))?;
+
     Ok(())
 }
 
-pub fn edit_group(house_name: String, data: BichoGroup) -> Result<(), String> {
-    if data.id == None { return  Err("Erro ao enviar ID".to_string());}
-    
+pub fn edit_group(lottery: String, data: BichoGroup) -> Result<(), String> {
+    let id = match data.id {
+        Some(id) => id,
+        None => return Err("Erro ao enviar ID".to_string()),
+    };
+
     let conn = get_connection();
-    let table_name = String::from("group_") + &format_database_name(&house_name);
-
-    let joined_str = data
-        .group
-        .iter()
-        .map(|num| num.to_string())
-        .collect::<Vec<String>>()
-        .join(", ");
-    
-    let query = format!(
-        "UPDATE {} SET hour = '{}', place = '{}', \"group\" = '{}', updated_at = strftime('%s', 'now') WHERE id = {}",
-        table_name, data.hour, data.place, joined_str, data.id.unwrap()
-    );
-
-    match conn.execute(&query, []) {
+
+    match GroupRepo::new(&conn).update(&lottery, id, &data) {
         Ok(_) => Ok(()),
-        Err(err) => {
-            if err.to_string().contains("UNIQUE constraint failed") {
-                return Err(format!("o grupo das {} horas do {}º premio já está cadastrado", data.hour, data.place));
-            }
-            return Err(err.to_string());
-        }
+        Err(err) => Err(group_error(&data, err)),
     }
 }
 
-pub fn delete_group(house_name: String, id: u32) -> Result<(), String> {
+pub fn delete_group(lottery: String, id: u32) -> Result<(), String> {
     let conn = get_connection();
-    let table_name = String::from("group_") + &format_database_name(&house_name);
 
-    let query = format!("DELETE FROM {} WHERE id = {}", table_name, id);
-
-    match conn.execute(&query, []) {
+    match GroupRepo::new(&conn).delete(&lottery, id) {
         Ok(affected_rows) => {
             if affected_rows > 0 {
                 Ok(())
@@ -363,108 +800,441 @@ pub fn delete_group(house_name: String, id: u32) -> Result<(), String> {
     }
 }
 
-fn parse_row_to_bicho_group(row: &Row) -> Result<BichoGroup, rusqlite::Error> {
-    let id: u32 = row.get("id")?;
-    let hour: String = row.get("hour")?;
-    let place: u32 = row.get("place")?;
-    let group_str: String = row.get("group")?;
-
-    // Divide a string `group` e converte para um vetor de u32
-    let group = group_str
-        .split(',')
-        .filter_map(|s| s.trim().parse::<u32>().ok())
-        .collect();
+pub fn get_hours(lottery: String) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let conn = get_connection();
 
-    Ok(BichoGroup { id: Some(id), hour, place, group })
+    Ok(DrawRepo::new(&conn).hours(&lottery)?)
 }
 
-pub fn get_hours(house_name: String) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+pub fn get_places(lottery: String) -> Result<Vec<u32>, Box<dyn std::error::Error>> {
     let conn = get_connection();
-    let table_name = format_database_name(&house_name);
 
-    let mut stmt = conn.prepare(&format!("SELECT DISTINCT hour FROM {}", table_name))?;
-    let rows = stmt.query_map([], |row| row.get(0))?;
+    Ok(DrawRepo::new(&conn).places(&lottery)?)
+}
 
-    let mut unique_values: Vec<String> = Vec::new();
-    for value in rows {
-        unique_values.push(value?);
+// Data de corte da sequência de perdas: aaaa-mm-dd (o dia inteiro),
+// aaaa-mm-dd HH:MM ou um timestamp. Os horários dos sorteios são de Brasília,
+// então o timestamp é lido nesse fuso.
+pub fn parse_as_of(value: &str) -> Result<Cutoff, String> {
+    let value = value.trim();
+    let midnight = |date: NaiveDate| date.and_hms_opt(0, 0, 0).expect("meia-noite existe").and_utc().timestamp();
+    let at = |datetime: NaiveDateTime| Cutoff {
+        date: midnight(datetime.date()),
+        hour: Some(datetime.format("%H:%M").to_string()),
+    };
+
+    if let Ok(timestamp) = value.parse::<i64>() {
+        let brasilia = FixedOffset::west_opt(3 * 3600).expect("fuso válido");
+        return DateTime::from_timestamp(timestamp, 0)
+            .map(|datetime| at(datetime.with_timezone(&brasilia).naive_local()))
+            .ok_or_else(|| format!("Timestamp de corte fora do intervalo: {}", timestamp));
+    }
+    if let Ok(datetime) = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M") {
+        return Ok(at(datetime));
     }
 
-    Ok(unique_values)
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| Cutoff {
+            date: midnight(date),
+            hour: None,
+        })
+        .map_err(|_| {
+            format!("Data de corte inválida: '{}'. Use aaaa-mm-dd, aaaa-mm-dd HH:MM ou um timestamp", value)
+        })
 }
 
-pub fn get_places(house_name: String) -> Result<Vec<u32>, Box<dyn std::error::Error>> {
+// Sequência de perdas de cada grupo cadastrado. Com `as_of`, como estava naquela data.
+pub fn get_loss_sequence(lottery: String, as_of: Option<Cutoff>) -> Result<Vec<LossSequence>, String> {
     let conn = get_connection();
-    let table_name = format_database_name(&house_name);
 
-    let mut stmt = conn.prepare(&format!("SELECT DISTINCT place FROM {}", table_name))?;
-    let rows = stmt.query_map([], |row| row.get(0))?;
+    // Uma consulta só para os sorteios; as secas saem de uma passada em memória
+    let groups = GroupRepo::new(&conn).list(&lottery).map_err(|e| e.to_string())?;
+    let history = History::new(DrawRepo::new(&conn).history(&lottery, as_of.as_ref()).map_err(|e| e.to_string())?);
 
-    let mut unique_values: Vec<u32> = Vec::new();
-    for value in rows {
-        unique_values.push(value?);
-    }
+    let results = groups
+        .into_iter()
+        .map(|data| {
+            let series = history.series(&data.hour, data.place);
+            loss_sequence_of(data, series)
+        })
+        .collect();
+
+    Ok(results)
+}
 
-    Ok(unique_values)
+fn loss_sequence_of(data: BichoGroup, series: &[DrawPoint]) -> LossSequence {
+    let droughts = analysis::droughts(series, &data.group);
+    // Se o grupo nunca saiu, a seca é ao menos todo o histórico
+    let length = droughts.current.unwrap_or(series.len());
+    let theoretical = analysis::theoretical_probability(&data.group, length);
+
+    LossSequence {
+        group: repo::join_groups(&data.group),
+        hour: data.hour,
+        place: data.place,
+        // Se o grupo nunca saiu, usa "999" como loss_sequence
+        loss_sequence: droughts.current.map_or(999, |drought| drought as i32),
+        theoretical_probability: theoretical,
+        empirical_probability: analysis::empirical_probability(&droughts.completed, length),
+        surprise: analysis::surprise(analysis::ranking_probability(theoretical, &droughts.completed, length)),
+    }
 }
 
-pub fn get_loss_sequence(house_name: String) -> Result<Vec<LossSequence>, String> {
+pub fn get_drought_stats(lottery: String) -> Result<Vec<GroupDroughtStats>, String> {
     let conn = get_connection();
-    let table_name = format_database_name(&house_name);
-
-    let query_groups_data = format!("SELECT hour, place, \"group\" FROM group_{}", table_name);
-    let mut stmt = conn.prepare(&query_groups_data).map_err(|e| e.to_string())?;
-
-    let groups = stmt.query_map([], |row| {
-        let hour: String = row.get(0)?;
-        let place: u32 = row.get(1)?;
-        let group: String = row.get(2)?;
-        
-        Ok((hour, place, group))
-    }).map_err(|e| e.to_string())?;
-    
-    let mut results: Vec<LossSequence> = Vec::new();
-    for group_data in groups {
-        let (hour, place, group) = group_data.map_err(|e| e.to_string())?;
-        
-        // Query para obter o último timestamp
-        let query_last_occurrence = format!(
-            "SELECT \"date\" FROM {} WHERE hour='{}' AND place='{}' AND \"group\" IN ({}) ORDER BY \"date\" DESC LIMIT 1",
-            table_name, hour, place, group
-        );
-        let last_timestamp: Option<i64> = conn.query_row(&query_last_occurrence, [], |row| row.get(0)).ok();
-        
-        // Se não houver uma última ocorrência, usa "999" como loss_sequence
-        let loss_sequence = if let Some(timestamp) = last_timestamp {
-            let query_loss_sequence = format!(
-                "SELECT count(*) FROM {} WHERE \"date\" > {} AND hour='{}' AND place='{}'",
-                table_name, timestamp, hour, place
-            );
-            
-            conn.query_row(&query_loss_sequence, [], |row| row.get(0)).unwrap_or(999)
-        } else {
-            999
-        };
 
-        results.push(LossSequence {
-            hour,
-            place,
-            group,
-            loss_sequence,
-        });
-    }
+    let groups = GroupRepo::new(&conn).list(&lottery).map_err(|e| e.to_string())?;
+    let history = History::new(DrawRepo::new(&conn).history(&lottery, None).map_err(|e| e.to_string())?);
+
+    let results = groups
+        .into_iter()
+        .map(|data| {
+            let droughts = analysis::droughts(history.series(&data.hour, data.place), &data.group);
+
+            GroupDroughtStats {
+                stats: analysis::drought_stats(&droughts.completed),
+                group: repo::join_groups(&data.group),
+                hour: data.hour,
+                place: data.place,
+            }
+        })
+        .collect();
 
     Ok(results)
 }
 
+// Cada vez que o grupo deu no horário e prêmio, com a seca que acabou ali.
+// Serve para qualquer grupo, cadastrado ou não.
+pub fn get_drought_timeline(lottery: String, data: &BichoGroup) -> Result<Vec<DroughtPoint>, String> {
+    let conn = get_connection();
+
+    let rows = DrawRepo::new(&conn)
+        .series(&lottery, &data.hour, data.place)
+        .map_err(|e| e.to_string())?;
+    let history = History::new(rows);
+
+    Ok(analysis::timeline(history.series(&data.hour, data.place), &data.group))
+}
+
+pub fn export_drought_timeline_to_csv(
+    lottery: String,
+    data: &BichoGroup,
+    file_path: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let timeline = get_drought_timeline(lottery, data)?;
+
+    let file = File::create(file_path)?;
+    let mut writer = BufWriter::new(file);
+
+    writeln!(writer, "hour,place,group,date,draws_before,complete")?;
+
+    // Os grupos vão entre aspas porque são separados por vírgula
+    let group = repo::join_groups(&data.group);
+    for point in timeline {
+        writeln!(
+            writer,
+            "{},{},\"{}\",{},{},{}",
+            data.hour, data.place, group, point.date, point.draws_before, point.complete
+        )?;
+    }
+
+    Ok(())
+}
 
 #[cfg(test)]
 mod testes {
-    use super::get_loss_sequence;
+    use super::{
+        add_group, adopt_house_tables, get_loss_sequence, house_table_name, loss_sequence_of, parse_as_of,
+        prepare_draw, resolve_group, save_bicho_data, BichoData, BichoDraw, BichoGroup,
+    };
+    use crate::modules::analysis::DrawPoint;
+    use crate::modules::conn::{self, DbLocation, DbPathSource};
+    use crate::modules::migrations;
+    use crate::modules::repo::{Cutoff, DrawRepo};
+    use crate::modules::web::House;
+    use rusqlite::Connection;
+    use std::sync::atomic::AtomicBool;
+    use std::time::Instant;
+
+    #[test]
+    fn house_table_name_is_a_valid_identifier() {
+        assert_eq!(house_table_name("zebra"), "house_zebra");
+        assert_eq!(house_table_name("pt-rio"), "house_pt_2d_rio");
+        assert_eq!(house_table_name("são joão (rj)"), "house_s_e3_o_20_jo_e3_o_20__28_rj_29_");
+    }
+
+    fn page(json_draws: &str, show_more: bool) -> BichoData {
+        serde_json::from_str(&format!(
+            r#"{{"bicho_lotteries_draws": [[{}]], "show_more": {}, "status": "ok"}}"#,
+            json_draws, show_more
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn merge_skips_repeated_draws() {
+        let draw_a = r#"{"place": "1", "lottery": {"title": null}, "thousand": "0452", "hour": "14:00", "group": "13", "date": "02/02/2024"}"#;
+        let draw_b = r#"{"place": "1", "lottery": {"title": null}, "thousand": "1234", "hour": "14:00", "group": "9", "date": "01/02/2024"}"#;
+
+        let mut merged = page(draw_a, true);
+        assert_eq!(merged.merge(page(&format!("{},{}", draw_a, draw_b), false)), 1);
+        assert_eq!(merged.merge(page(draw_b, false)), 0);
+
+        assert_eq!(merged.total_draws(), 2);
+        assert!(!merged.show_more);
+        assert_eq!(merged.oldest_date(), chrono::NaiveDate::from_ymd_opt(2024, 2, 1));
+    }
+
+    #[test]
+    fn legacy_draws_get_the_group_from_the_milhar() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::migrate(&mut conn).unwrap();
+        // Tabela como as criadas antes das migrações, com o milhar como número
+        conn.execute_batch(
+            "INSERT INTO houses (value, name, position, first_seen, last_seen) VALUES ('zebra', 'A Zebra', 0, 0, 0);
+             CREATE TABLE A_Zebra (
+                id INTEGER PRIMARY KEY,
+                place INTEGER NOT NULL,
+                date INTEGER NOT NULL,
+                hour TEXT NOT NULL,
+                milhar INTEGER NOT NULL,
+                \"group\" INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+                UNIQUE (place, date, hour)
+             );
+             INSERT INTO A_Zebra (place, date, hour, milhar, \"group\") VALUES (1, 0, '14:00', '0452', 9);
+             INSERT INTO A_Zebra (place, date, hour, milhar, \"group\") VALUES (2, 0, '14:00', '1300', 25);
+             INSERT INTO A_Zebra (place, date, hour, milhar, \"group\") VALUES (3, 0, '14:00', '999', 999);
+             INSERT INTO A_Zebra (place, date, hour, milhar, \"group\") VALUES (4, 0, '14:00', '999', 7);
+             INSERT INTO A_Zebra (place, date, hour, milhar, \"group\") VALUES (5, 0, '14:00', '0452', 999);",
+        )
+        .unwrap();
+        let house = |name: &str, value: &str| House {
+            name: name.to_string(),
+            value: value.to_string(),
+            disappeared: false,
+        };
+
+        assert!(adopt_house_tables(&conn, &house("A Zebra", "zebra")).unwrap());
+        // Uma casa com o nome de uma tabela do app não leva a tabela junto
+        assert!(!adopt_house_tables(&conn, &house("draws", "draws")).unwrap());
+
+        let mut stmt = conn
+            .prepare("SELECT milhar, \"group\", scraped_group FROM draws ORDER BY place")
+            .unwrap();
+        let rows: Vec<(String, i64, Option<String>)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                ("0452".to_string(), 13, Some("9".to_string())),
+                ("1300".to_string(), 25, None),
+                ("999".to_string(), 999, None),
+                ("999".to_string(), 7, None),
+                // O 999 no grupo é só ausente, não uma divergência do site
+                ("0452".to_string(), 13, None),
+            ]
+        );
+        assert_eq!(DrawRepo::new(&conn).flagged(Some("zebra")).unwrap().len(), 1);
+        // O marcador de milhar ausente fica sem dezena e centena
+        let filled: i64 = conn
+            .query_row("SELECT count(*) FROM draws WHERE milhar = '999' AND dezena || centena <> ''", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(filled, 0);
+    }
+
+    #[test]
+    fn scraped_group_is_kept_only_when_it_disagrees() {
+        assert_eq!(resolve_group(Some("0452"), Some("13")), ("13".to_string(), None));
+        assert_eq!(resolve_group(Some("0452"), Some("09")), ("13".to_string(), Some("09".to_string())));
+        assert_eq!(resolve_group(Some("0452"), None), ("13".to_string(), None));
+        assert_eq!(resolve_group(None, Some("9")), ("9".to_string(), None));
+    }
+
+    #[test]
+    fn unknown_fields_survive_a_round_trip() {
+        let raw = r#"{"place": "1", "lottery": {"title": null, "slug": "pt-rio"}, "thousand": "0452", "hour": "14:00", "group": "13", "date": "02/02/2024", "modality": {"name": "PTM"}}"#;
+        let draw: BichoDraw = serde_json::from_str(raw).unwrap();
+
+        let expected: serde_json::Value = serde_json::from_str(raw).unwrap();
+        assert_eq!(serde_json::to_value(&draw).unwrap(), expected);
+    }
+
+    #[test]
+    fn strict_mode_rejects_incomplete_draws() {
+        let draw: BichoDraw = serde_json::from_str(
+            r#"{"place": "1", "lottery": {"title": null}, "thousand": null, "hour": "14:00", "group": null, "date": "31/02/2024"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            prepare_draw(&draw, true).unwrap_err(),
+            "milhar ausente, grupo ausente, data ausente ou inválida"
+        );
+
+        // Fora do modo estrito continua gravando com os valores de sempre
+        let row = prepare_draw(&draw, false).unwrap();
+        assert_eq!((row.milhar.as_str(), row.date.as_str()), ("999", "2024-02-31"));
+        // Mas o marcador não ganha dezena e centena
+        assert_eq!((row.dezena.as_str(), row.centena.as_str()), ("", ""));
+    }
 
+    #[test]
+    fn loss_sequence_counts_draws_since_the_last_hit() {
+        let series: Vec<DrawPoint> = [5, 9, 3, 12, 5, 7]
+            .iter()
+            .enumerate()
+            .map(|(day, &group)| DrawPoint { date: day as i64, group })
+            .collect();
+        let data = |group: Vec<u32>| BichoGroup {
+            id: Some(1),
+            hour: "14:00".to_string(),
+            place: 1,
+            group,
+        };
+
+        let loss = loss_sequence_of(data(vec![9, 12]), &series);
+        assert_eq!((loss.group.as_str(), loss.loss_sequence), ("9, 12", 2));
+        assert_eq!(loss_sequence_of(data(vec![7]), &series).loss_sequence, 0);
+        assert!((loss.theoretical_probability - 0.92 * 0.92).abs() < 1e-12);
+        // A única seca terminada, de 1 sorteio, foi menor que a atual
+        assert_eq!(loss.empirical_probability, Some(0.0));
+        // Com tão poucas secas terminadas, a surpresa sai da chance teórica
+        assert!((loss.surprise - (0.92f64 * 0.92).recip().log10()).abs() < 1e-12);
+
+        // Grupo que nunca saiu: a seca é todo o histórico
+        let never = loss_sequence_of(data(vec![25]), &series);
+        assert_eq!(never.loss_sequence, 999);
+        assert!((never.theoretical_probability - 0.96f64.powi(6)).abs() < 1e-12);
+        assert_eq!(never.empirical_probability, None);
+        assert!(never.surprise > loss.surprise);
+    }
+
+    #[test]
+    fn as_of_accepts_a_date_or_a_timestamp() {
+        let cutoff = |date, hour: Option<&str>| Ok(Cutoff { date, hour: hour.map(String::from) });
+
+        assert_eq!(parse_as_of("2024-02-01"), cutoff(1706745600, None));
+        assert_eq!(parse_as_of("2024-02-01 14:30"), cutoff(1706745600, Some("14:30")));
+        // Meia-noite UTC ainda é 21:00 do dia anterior em Brasília
+        assert_eq!(parse_as_of(" 1706745600 "), cutoff(1706659200, Some("21:00")));
+        assert!(parse_as_of("01/02/2024").is_err());
+    }
+
+    // Os benchmarks trocam o banco global, então rode um por vez:
+    // `cargo test --release <nome> -- --ignored --nocapture --test-threads=1`
+    fn bench_db(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("get_bicho_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        conn::open_db(DbLocation {
+            path: dir.join("bench.db"),
+            source: DbPathSource::Workspace,
+        })
+        .unwrap();
+
+        dir
+    }
+
+    // JSON como o do site com `days` dias de 5 horários e 5 prêmios, a partir de 2020
+    const BENCH_HOURS: [&str; 5] = ["11:00", "14:00", "16:00", "18:00", "21:00"];
+
+    fn synthetic_backfill(days: i64) -> (String, usize) {
+        let start = chrono::NaiveDate::from_ymd_opt(2020, 1, 1).unwrap();
+        let mut draws = Vec::new();
+        for day in 0..days {
+            let date = (start + chrono::Duration::days(day)).format("%d/%m/%Y");
+            for (index, hour) in BENCH_HOURS.iter().enumerate() {
+                for place in 1..=5 {
+                    let milhar = format!("{:04}", (day * 7919 + place * 1013 + index as i64 * 104729) % 10000);
+                    draws.push(format!(
+                        r#"{{"place": "{}", "lottery": {{"title": null}}, "thousand": "{}", "hour": "{}", "group": null, "date": "{}"}}"#,
+                        place, milhar, hour, date
+                    ));
+                }
+            }
+        }
+        let json = format!(
+            r#"{{"bicho_lotteries_draws": [[{}]], "show_more": false, "status": "ok"}}"#,
+            draws.join(",")
+        );
+
+        (json, draws.len())
+    }
+
+    // Backfill de 1600 dias gravado duas vezes
+    #[test]
+    #[ignore]
+    fn bulk_save_throughput() {
+        let dir = bench_db("bench");
+        let (json, total) = synthetic_backfill(1600);
+
+        for pass in ["inserção", "repetição"] {
+            let started = Instant::now();
+            let report = save_bicho_data("bench".to_string(), &json, true, &AtomicBool::new(false), |_| {}).unwrap();
+            let secs = started.elapsed().as_secs_f64();
+            println!(
+                "{}: {} sorteios em {:.2}s ({:.0}/s) {:?}",
+                pass,
+                total,
+                secs,
+                total as f64 / secs,
+                report
+            );
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    // Sequência de perdas de 4 casas, com 25 grupos cada, sobre 5 anos de sorteios
+    #[test]
+    #[ignore]
+    fn loss_sequence_throughput() {
+        let dir = bench_db("loss");
+        let (json, total) = synthetic_backfill(5 * 365);
+        let houses: Vec<String> = (1..=4).map(|house| format!("bench{}", house)).collect();
+
+        for (size, house) in houses.iter().enumerate() {
+            save_bicho_data(house.clone(), &json, true, &AtomicBool::new(false), |_| {}).unwrap();
+            for hour in BENCH_HOURS {
+                for place in 1..=5 {
+                    let group = BichoGroup {
+                        id: None,
+                        hour: hour.to_string(),
+                        place,
+                        group: (0..=size as u32).map(|group| group * 5 + place).collect(),
+                    };
+                    add_group(house.clone(), group).unwrap();
+                }
+            }
+        }
+
+        let runs = 10;
+        let started = Instant::now();
+        for _ in 0..runs {
+            for house in houses.iter() {
+                assert_eq!(get_loss_sequence(house.clone(), None).unwrap().len(), 25);
+            }
+        }
+        let secs = started.elapsed().as_secs_f64() / runs as f64;
+        println!(
+            "100 grupos em {} casas com {} sorteios cada: {:.1}ms",
+            houses.len(),
+            total,
+            secs * 1000.0
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 
+    // Para depurar à mão, com um banco aberto
+    #[allow(dead_code)]
     fn test_get_loss_sequence() {
-        let a = get_loss_sequence("A Zebra".to_owned()).unwrap();
+        let a = get_loss_sequence("A Zebra".to_owned(), None).unwrap();
         println!("saida: {:?}", a);
     }
 }
\ No newline at end of file
@@ -0,0 +1,247 @@
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+lazy_static! {
+    // Cliente compartilhado por todas as requisições ao bicho365
+    pub static ref CLIENT: HttpClient =
+        HttpClient::new(HttpConfig::from_env()).expect("Falha ao criar o cliente HTTP");
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    pub timeout: Duration,
+    pub connect_timeout: Duration,
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub min_interval: Duration, // Intervalo mínimo entre requisições ao mesmo host
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(10),
+            max_retries: 4,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            min_interval: Duration::from_millis(250),
+        }
+    }
+}
+
+impl HttpConfig {
+    // Padrões que podem ser trocados pelas variáveis `BICHO_HTTP_*`
+    pub fn from_env() -> Self {
+        let mut config = HttpConfig::default();
+        let read = |name: &str| {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.parse::<u64>().ok())
+        };
+
+        if let Some(secs) = read("BICHO_HTTP_TIMEOUT_SECS") {
+            config.timeout = Duration::from_secs(secs);
+        }
+        if let Some(secs) = read("BICHO_HTTP_CONNECT_TIMEOUT_SECS") {
+            config.connect_timeout = Duration::from_secs(secs);
+        }
+        if let Some(retries) = read("BICHO_HTTP_MAX_RETRIES") {
+            config.max_retries = retries as u32;
+        }
+        if let Some(millis) = read("BICHO_HTTP_MIN_INTERVAL_MS") {
+            config.min_interval = Duration::from_millis(millis);
+        }
+
+        config
+    }
+
+    // Espera antes da tentativa `attempt` (começando em 1), dobrando a cada vez
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+pub struct HttpClient {
+    client: reqwest::Client,
+    config: HttpConfig,
+    next_slot: Mutex<HashMap<String, Instant>>, // Próximo horário livre de cada host
+}
+
+impl HttpClient {
+    pub fn new(config: HttpConfig) -> Result<Self, reqwest::Error> {
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .connect_timeout(config.connect_timeout)
+            .build()?;
+
+        Ok(HttpClient {
+            client,
+            config,
+            next_slot: Mutex::new(HashMap::new()),
+        })
+    }
+
+    // Reserva a vez do host e espera até ela chegar
+    async fn wait_turn(&self, host: &str) {
+        let slot = {
+            let mut next_slot = self.next_slot.lock().await;
+            let now = Instant::now();
+            let slot = next_slot.get(host).map_or(now, |next| (*next).max(now));
+            next_slot.insert(host.to_string(), slot + self.config.min_interval);
+            slot
+        };
+
+        tokio::time::sleep_until(slot).await;
+    }
+
+    // GET com novas tentativas em erros 5xx e timeouts
+    pub async fn get_text(&self, url: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let host = reqwest::Url::parse(url)?
+            .host_str()
+            .unwrap_or_default()
+            .to_string();
+
+        let mut attempt = 0;
+        loop {
+            self.wait_turn(&host).await;
+
+            // Só erros 5xx e timeouts chegam aqui, o resto retorna direto
+            let err: Box<dyn Error + Send + Sync> = match self.client.get(url).send().await {
+                Ok(response) if response.status().is_server_error() => {
+                    format!("Erro {} em {}", response.status(), url).into()
+                }
+                Ok(response) => match response.error_for_status() {
+                    Ok(response) => match response.text().await {
+                        Ok(body) => return Ok(body),
+                        Err(err) if err.is_timeout() => err.into(),
+                        Err(err) => return Err(err.into()),
+                    },
+                    Err(err) => return Err(err.into()),
+                },
+                Err(err) if err.is_timeout() => err.into(),
+                Err(err) => return Err(err.into()),
+            };
+
+            attempt += 1;
+            if attempt > self.config.max_retries {
+                return Err(err);
+            }
+
+            tokio::time::sleep(self.config.backoff(attempt)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod testes {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // Servidor HTTP local que responde cada requisição com o próximo item
+    // de `responses` (status, corpo, atraso). Retorna a URL e o contador de acessos.
+    async fn mock_server(
+        responses: Vec<(u16, &'static str, Duration)>,
+    ) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let index = counter.fetch_add(1, Ordering::SeqCst);
+                let (status, body, delay) = responses[index.min(responses.len() - 1)];
+
+                tokio::spawn(async move {
+                    let mut buffer = [0u8; 1024];
+                    let _ = socket.read(&mut buffer).await;
+                    tokio::time::sleep(delay).await;
+                    let response = format!(
+                        "HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    );
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+
+        (url, hits)
+    }
+
+    fn test_config() -> HttpConfig {
+        HttpConfig {
+            timeout: Duration::from_millis(300),
+            connect_timeout: Duration::from_millis(300),
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(50),
+            min_interval: Duration::ZERO,
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_and_timeouts() {
+        let (url, hits) = mock_server(vec![
+            (500, "erro", Duration::ZERO),
+            (200, "lento", Duration::from_secs(1)),
+            (200, "ok", Duration::ZERO),
+        ])
+        .await;
+        let client = HttpClient::new(test_config()).unwrap();
+
+        assert_eq!(client.get_text(&url).await.unwrap(), "ok");
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let (url, hits) = mock_server(vec![(404, "não existe", Duration::ZERO)]).await;
+        let client = HttpClient::new(test_config()).unwrap();
+
+        assert!(client.get_text(&url).await.is_err());
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let (url, hits) = mock_server(vec![(503, "fora", Duration::ZERO)]).await;
+        let client = HttpClient::new(test_config()).unwrap();
+
+        assert!(client.get_text(&url).await.is_err());
+        assert_eq!(hits.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn spaces_requests_to_the_same_host() {
+        let (url, _) = mock_server(vec![(200, "ok", Duration::ZERO)]).await;
+        let config = HttpConfig {
+            min_interval: Duration::from_millis(100),
+            ..test_config()
+        };
+        let client = HttpClient::new(config).unwrap();
+
+        let started = Instant::now();
+        let (a, b, c) = tokio::join!(
+            client.get_text(&url),
+            client.get_text(&url),
+            client.get_text(&url)
+        );
+        assert!(a.is_ok() && b.is_ok() && c.is_ok());
+        assert!(started.elapsed() >= Duration::from_millis(200));
+    }
+}
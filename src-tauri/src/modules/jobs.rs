@@ -0,0 +1,157 @@
+use chrono::Utc;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// Quantos jobs terminados ficam guardados para o `list_jobs`
+const FINISHED_JOBS_KEPT: usize = 50;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct JobInfo {
+    pub id: u64,
+    pub house: String,
+    pub status: JobStatus,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    pub error: Option<String>,
+}
+
+struct Job {
+    info: JobInfo,
+    cancel: Arc<AtomicBool>,
+}
+
+lazy_static! {
+    static ref JOBS: Mutex<HashMap<u64, Job>> = Mutex::new(HashMap::new());
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+// Referência a um job em execução, usada para checar o cancelamento
+#[derive(Clone)]
+pub struct JobHandle {
+    pub id: u64,
+    cancel: Arc<AtomicBool>,
+}
+
+impl JobHandle {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    pub fn cancel_flag(&self) -> &AtomicBool {
+        &self.cancel
+    }
+
+    // Termina só quando o job for cancelado, para usar com `tokio::select!`
+    pub async fn cancelled(&self) {
+        while !self.is_cancelled() {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    }
+}
+
+pub fn start_job(house: &str) -> JobHandle {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let cancel = Arc::new(AtomicBool::new(false));
+
+    let job = Job {
+        info: JobInfo {
+            id,
+            house: house.to_string(),
+            status: JobStatus::Running,
+            started_at: Utc::now().timestamp(),
+            finished_at: None,
+            error: None,
+        },
+        cancel: cancel.clone(),
+    };
+    JOBS.lock().expect("Falha ao travar os jobs").insert(id, job);
+
+    JobHandle { id, cancel }
+}
+
+pub fn finish_job<T>(handle: &JobHandle, result: &Result<T, String>) {
+    let mut jobs = JOBS.lock().expect("Falha ao travar os jobs");
+
+    if let Some(job) = jobs.get_mut(&handle.id) {
+        job.info.finished_at = Some(Utc::now().timestamp());
+        job.info.status = match result {
+            Ok(_) => JobStatus::Completed,
+            Err(_) if handle.is_cancelled() => JobStatus::Cancelled,
+            Err(_) => JobStatus::Failed,
+        };
+        job.info.error = result.as_ref().err().cloned();
+    }
+
+    // Descarta os jobs terminados mais antigos
+    let mut finished: Vec<(i64, u64)> = jobs
+        .values()
+        .filter(|job| job.info.status != JobStatus::Running)
+        .map(|job| (job.info.finished_at.unwrap_or(0), job.info.id))
+        .collect();
+    if finished.len() > FINISHED_JOBS_KEPT {
+        finished.sort();
+        for (_, id) in finished.iter().take(finished.len() - FINISHED_JOBS_KEPT) {
+            jobs.remove(id);
+        }
+    }
+}
+
+pub fn cancel_job(id: u64) -> Result<(), String> {
+    let jobs = JOBS.lock().expect("Falha ao travar os jobs");
+
+    match jobs.get(&id) {
+        Some(job) if job.info.status == JobStatus::Running => {
+            job.cancel.store(true, Ordering::Relaxed);
+            Ok(())
+        }
+        Some(_) => Err(format!("O job {} já terminou", id)),
+        None => Err(format!("Nenhum job encontrado com o id {}", id)),
+    }
+}
+
+pub fn has_running_jobs() -> bool {
+    let jobs = JOBS.lock().expect("Falha ao travar os jobs");
+
+    jobs.values().any(|job| job.info.status == JobStatus::Running)
+}
+
+pub fn list_jobs() -> Vec<JobInfo> {
+    let jobs = JOBS.lock().expect("Falha ao travar os jobs");
+
+    let mut list: Vec<JobInfo> = jobs.values().map(|job| job.info.clone()).collect();
+    list.sort_by_key(|info| info.id);
+
+    list
+}
+
+#[cfg(test)]
+mod testes {
+    use super::*;
+
+    #[test]
+    fn cancelled_job_is_reported_as_cancelled() {
+        let job = start_job("Casa Teste");
+        cancel_job(job.id).unwrap();
+        assert!(job.is_cancelled());
+
+        finish_job::<()>(&job, &Err("cancelado".to_string()));
+        let info = list_jobs().into_iter().find(|info| info.id == job.id).unwrap();
+        assert_eq!(info.status, JobStatus::Cancelled);
+
+        assert!(cancel_job(job.id).is_err());
+    }
+}
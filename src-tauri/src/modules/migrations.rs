@@ -0,0 +1,339 @@
+use rusqlite::Connection;
+
+// Uma alteração do esquema do banco. A versão aplicada fica salva no
+// `PRAGMA user_version` do próprio arquivo.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+// Migrações em ordem. Nunca altere uma que já foi publicada, crie uma nova.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "tabelas de casas, sorteios, grupos e estado de sincronização",
+        // Bancos anteriores às migrações já podem ter estas tabelas
+        sql: "CREATE TABLE IF NOT EXISTS houses (
+                value TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                position INTEGER NOT NULL,
+                first_seen INTEGER NOT NULL,
+                last_seen INTEGER NOT NULL,
+                disappeared_at INTEGER
+            );
+
+            CREATE TABLE IF NOT EXISTS draws (
+                id INTEGER PRIMARY KEY,
+                house_id TEXT NOT NULL REFERENCES houses (value),
+                place INTEGER NOT NULL,
+                date INTEGER NOT NULL,
+                hour TEXT NOT NULL,
+                milhar INTEGER NOT NULL,
+                \"group\" INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+                UNIQUE (house_id, place, date, hour)
+            );
+            CREATE INDEX IF NOT EXISTS draws_house_hour_place_date ON draws (house_id, hour, place, date);
+            CREATE INDEX IF NOT EXISTS draws_house_date_hour ON draws (house_id, date, hour);
+
+            CREATE TABLE IF NOT EXISTS groups (
+                id INTEGER PRIMARY KEY,
+                house_id TEXT NOT NULL REFERENCES houses (value),
+                hour TEXT NOT NULL,
+                place INTEGER NOT NULL,
+                \"group\" TEXT NOT NULL,
+                updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+                UNIQUE (house_id, hour, place)
+            );
+
+            CREATE TABLE IF NOT EXISTS sync_state (
+                house TEXT PRIMARY KEY,
+                last_draw_date INTEGER,
+                last_draw_hour TEXT,
+                last_synced_at INTEGER
+            );",
+    },
+    Migration {
+        version: 2,
+        description: "milhar como texto, com dezena e centena",
+        // Milhares salvos como número perderam os zeros à esquerda. O 999 era o
+        // marcador de milhar ausente e fica sem zeros, a não ser que o grupo 25
+        // mostre que era mesmo o 0999.
+        sql: "CREATE TABLE draws_new (
+                id INTEGER PRIMARY KEY,
+                house_id TEXT NOT NULL REFERENCES houses (value),
+                place INTEGER NOT NULL,
+                date INTEGER NOT NULL,
+                hour TEXT NOT NULL,
+                milhar TEXT NOT NULL,
+                dezena TEXT NOT NULL,
+                centena TEXT NOT NULL,
+                \"group\" INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+                UNIQUE (house_id, place, date, hour)
+            );
+
+            INSERT INTO draws_new (id, house_id, place, date, hour, milhar, dezena, centena, \"group\", updated_at)
+            SELECT id, house_id, place, date, hour, milhar, substr(milhar, -2), substr(milhar, -3), \"group\", updated_at
+            FROM (
+                SELECT id, house_id, place, date, hour, \"group\", updated_at,
+                    CASE WHEN typeof(milhar) = 'integer' AND (milhar <> 999 OR \"group\" = 25)
+                        THEN printf('%04d', milhar) ELSE milhar END AS milhar
+                FROM draws
+            );
+
+            DROP TABLE draws;
+            ALTER TABLE draws_new RENAME TO draws;
+
+            CREATE INDEX draws_house_hour_place_date ON draws (house_id, hour, place, date);
+            CREATE INDEX draws_house_date_hour ON draws (house_id, date, hour);
+            CREATE INDEX draws_house_dezena ON draws (house_id, dezena);
+            CREATE INDEX draws_house_centena ON draws (house_id, centena);",
+    },
+    Migration {
+        version: 3,
+        description: "grupo calculado pelo milhar, guardando o do site quando diverge",
+        // O grupo vem da dezena: 01-04 é o 1, ..., 97-00 é o 25. O marcador "999"
+        // de milhar ausente não tem grupo a calcular, e o grupo 999 é só um
+        // grupo ausente, então não conta como divergência.
+        sql: "ALTER TABLE draws ADD COLUMN scraped_group TEXT;
+
+            UPDATE draws SET scraped_group = NULLIF(\"group\", 999), \"group\" = derived
+            FROM (
+                SELECT id AS draw_id,
+                    CASE WHEN CAST(dezena AS INTEGER) = 0 THEN 25 ELSE (CAST(dezena AS INTEGER) + 3) / 4 END AS derived
+                FROM draws
+                WHERE length(milhar) >= 2 AND milhar NOT GLOB '*[^0-9]*' AND milhar <> '999'
+            )
+            WHERE draws.id = draw_id AND draws.\"group\" IS NOT derived;
+
+            CREATE INDEX draws_house_scraped_group ON draws (house_id) WHERE scraped_group IS NOT NULL;",
+    },
+    Migration {
+        version: 4,
+        description: "quarentena dos sorteios rejeitados no modo estrito",
+        sql: "CREATE TABLE quarantine (
+                id INTEGER PRIMARY KEY,
+                house_id TEXT NOT NULL REFERENCES houses (value),
+                reason TEXT NOT NULL,
+                raw TEXT NOT NULL,
+                created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+                UNIQUE (house_id, raw)
+            );",
+    },
+    Migration {
+        version: 5,
+        description: "histórico das correções de sorteios feitas pelo site",
+        // O gatilho pega qualquer UPDATE que mude o resultado, venha de onde vier
+        sql: "CREATE TABLE draw_revisions (
+                id INTEGER PRIMARY KEY,
+                draw_id INTEGER NOT NULL REFERENCES draws (id),
+                house_id TEXT NOT NULL REFERENCES houses (value),
+                old_milhar TEXT NOT NULL,
+                new_milhar TEXT NOT NULL,
+                old_group INTEGER NOT NULL,
+                new_group INTEGER NOT NULL,
+                old_scraped_group TEXT,
+                new_scraped_group TEXT,
+                revised_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+            );
+            CREATE INDEX draw_revisions_house ON draw_revisions (house_id, id);
+
+            CREATE TRIGGER draws_revision AFTER UPDATE OF milhar, \"group\", scraped_group ON draws
+            WHEN OLD.milhar IS NOT NEW.milhar
+                OR OLD.\"group\" IS NOT NEW.\"group\"
+                OR OLD.scraped_group IS NOT NEW.scraped_group
+            BEGIN
+                INSERT INTO draw_revisions (draw_id, house_id, old_milhar, new_milhar, old_group, new_group,
+                    old_scraped_group, new_scraped_group)
+                VALUES (NEW.id, NEW.house_id, OLD.milhar, NEW.milhar, OLD.\"group\", NEW.\"group\",
+                    OLD.scraped_group, NEW.scraped_group);
+            END;",
+    },
+    Migration {
+        version: 6,
+        description: "arquivo das respostas da fonte, comprimidas",
+        sql: "CREATE TABLE raw_payloads (
+                id INTEGER PRIMARY KEY,
+                house_id TEXT NOT NULL REFERENCES houses (value),
+                url TEXT NOT NULL,
+                fetched_at INTEGER NOT NULL,
+                sha256 TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                body BLOB NOT NULL,
+                UNIQUE (house_id, sha256)
+            );
+            CREATE INDEX raw_payloads_house_fetched_at ON raw_payloads (house_id, fetched_at);",
+    },
+    Migration {
+        version: 7,
+        description: "grupo no índice de horário, prêmio e data",
+        // Assim o histórico das análises sai só do índice, sem ler a tabela
+        sql: "DROP INDEX draws_house_hour_place_date;
+            CREATE INDEX draws_house_hour_place_date_group ON draws (house_id, hour, place, date, \"group\");",
+    },
+    Migration {
+        version: 8,
+        description: "dezena e centena vazias no marcador de milhar ausente",
+        // O "999" de milhar ausente ficava com a dezena 99 e a centena 999,
+        // como se fosse um milhar de verdade
+        sql: "UPDATE draws SET dezena = '', centena = '' WHERE milhar = '999';",
+    },
+    Migration {
+        version: 9,
+        description: "correções gravadas pela ingestão, sem o gatilho",
+        // O gatilho contava qualquer UPDATE, até um marcador preenchido ou o
+        // grupo do site recalculado. Agora quem grava é o `DrawRepo::upsert`.
+        sql: "DROP TRIGGER draws_revision;",
+    },
+];
+
+// Tabelas criadas pelas migrações. Nunca são tratadas como tabelas antigas de
+// uma casa, mesmo que uma casa tenha o mesmo nome.
+pub const APP_TABLES: &[&str] = &[
+    "houses",
+    "draws",
+    "draws_new",
+    "groups",
+    "sync_state",
+    "quarantine",
+    "draw_revisions",
+    "raw_payloads",
+];
+
+// Versão do esquema que esta versão do app conhece
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+pub fn schema_version(conn: &Connection) -> Result<u32, rusqlite::Error> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+// Aplica as migrações pendentes, cada uma na sua transação, e retorna a
+// versão final. Recusa bancos gravados por uma versão mais nova do app.
+pub fn migrate(conn: &mut Connection) -> Result<u32, String> {
+    let current = schema_version(conn).map_err(|err| err.to_string())?;
+    let latest = latest_version();
+
+    if current > latest {
+        return Err(format!(
+            "O banco de dados está na versão {} do esquema, mas este app só conhece até a versão {}. Atualize o app para abri-lo.",
+            current, latest
+        ));
+    }
+
+    // Migrações que recriam tabelas não podem esbarrar nas chaves estrangeiras
+    // no meio do caminho, então elas são conferidas só no fim
+    conn.execute_batch("PRAGMA foreign_keys = OFF")
+        .map_err(|err| err.to_string())?;
+    let result = apply_pending(conn, current);
+    conn.execute_batch("PRAGMA foreign_keys = ON")
+        .map_err(|err| err.to_string())?;
+
+    result
+}
+
+fn apply_pending(conn: &mut Connection, current: u32) -> Result<u32, String> {
+    let mut version = current;
+
+    for migration in MIGRATIONS.iter().filter(|migration| migration.version > current) {
+        println!("Aplicando a migração {}: {}", migration.version, migration.description);
+
+        apply(conn, migration).map_err(|err| {
+            format!("Erro na migração {} ({}): {}", migration.version, migration.description, err)
+        })?;
+
+        version = migration.version;
+    }
+
+    Ok(version)
+}
+
+fn apply(conn: &mut Connection, migration: &Migration) -> Result<(), String> {
+    let tx = conn.transaction().map_err(|err| err.to_string())?;
+    tx.execute_batch(migration.sql).map_err(|err| err.to_string())?;
+
+    let violations: i64 = tx
+        .query_row("SELECT count(*) FROM pragma_foreign_key_check", [], |row| row.get(0))
+        .map_err(|err| err.to_string())?;
+    if violations > 0 {
+        // Sair sem o commit desfaz a migração
+        return Err(format!("{} linhas com chave estrangeira inválida", violations));
+    }
+
+    tx.execute_batch(&format!("PRAGMA user_version = {}", migration.version))
+        .map_err(|err| err.to_string())?;
+    tx.commit().map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod testes {
+    use super::*;
+
+    #[test]
+    fn migrations_are_numbered_in_order() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, index as u32 + 1);
+        }
+    }
+
+    #[test]
+    fn migrate_brings_new_database_to_latest_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        assert_eq!(migrate(&mut conn).unwrap(), latest_version());
+        assert_eq!(schema_version(&conn).unwrap(), latest_version());
+
+        // Rodar de novo não muda nada
+        assert_eq!(migrate(&mut conn).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn old_draws_are_backfilled() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0].sql).unwrap();
+        conn.execute_batch(
+            "PRAGMA user_version = 1;
+             INSERT INTO houses (value, name, position, first_seen, last_seen) VALUES ('zebra', 'A Zebra', 0, 0, 0);
+             INSERT INTO draws (house_id, place, date, hour, milhar, \"group\") VALUES ('zebra', 1, 0, '14:00', '0452', 9);
+             INSERT INTO draws (house_id, place, date, hour, milhar, \"group\") VALUES ('zebra', 2, 0, '14:00', '1300', 25);
+             INSERT INTO draws (house_id, place, date, hour, milhar, \"group\") VALUES ('zebra', 3, 0, '14:00', '999', 999);
+             INSERT INTO draws (house_id, place, date, hour, milhar, \"group\") VALUES ('zebra', 4, 0, '14:00', '999', 7);
+             INSERT INTO draws (house_id, place, date, hour, milhar, \"group\") VALUES ('zebra', 5, 0, '14:00', '0999', 25);
+             INSERT INTO draws (house_id, place, date, hour, milhar, \"group\") VALUES ('zebra', 6, 0, '14:00', '0452', 999);",
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+        let mut stmt = conn
+            .prepare("SELECT milhar, dezena, centena, \"group\", scraped_group FROM draws ORDER BY place")
+            .unwrap();
+        let rows: Vec<(String, String, String, i64, Option<String>)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        // Milhar com os zeros de volta e o grupo errado do site guardado à parte
+        assert_eq!(rows[0], ("0452".into(), "52".into(), "452".into(), 13, Some("9".into())));
+        assert_eq!(rows[1], ("1300".into(), "00".into(), "300".into(), 25, None));
+        // Marcadores de milhar ausente continuam como estavam, sem dezena e centena
+        assert_eq!(rows[2], ("999".into(), "".into(), "".into(), 999, None));
+        assert_eq!(rows[3], ("999".into(), "".into(), "".into(), 7, None));
+        assert_eq!(rows[4], ("0999".into(), "99".into(), "999".into(), 25, None));
+        // Grupo ausente (999) é só preenchido, não é divergência
+        assert_eq!(rows[5], ("0452".into(), "52".into(), "452".into(), 13, None));
+    }
+
+    #[test]
+    fn migrate_refuses_newer_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(&format!("PRAGMA user_version = {}", latest_version() + 1))
+            .unwrap();
+
+        assert!(migrate(&mut conn).is_err());
+        assert_eq!(schema_version(&conn).unwrap(), latest_version() + 1);
+    }
+}
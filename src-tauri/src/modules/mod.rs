@@ -1,3 +1,14 @@
+pub mod analysis;
+pub mod archive;
 pub mod conn;
 pub mod db;
-pub mod web;
\ No newline at end of file
+pub mod http;
+pub mod jobs;
+pub mod migrations;
+pub mod progress;
+pub mod repo;
+pub mod settings;
+pub mod source;
+pub mod sync;
+pub mod web;
+pub mod workspace;
\ No newline at end of file
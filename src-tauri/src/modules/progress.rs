@@ -0,0 +1,147 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Intervalo mínimo entre dois eventos de progresso da mesma fase
+const EMIT_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Download,
+    Parse,
+    Insert,
+    Analyze,
+}
+
+// O que cada etapa informa: quanto já foi feito da fase atual
+#[derive(Debug, Clone, Copy)]
+pub struct ProgressStep {
+    pub phase: Phase,
+    pub processed: usize,
+    pub total: usize,
+    pub rejected: usize,
+}
+
+// Evento enviado para o frontend
+#[derive(Serialize, Debug, Clone)]
+pub struct Progress {
+    pub job_id: u64,
+    pub house: String,
+    pub phase: Phase,
+    pub processed: usize,
+    pub total: usize,
+    pub rejected: usize,
+    pub eta_secs: Option<f64>,
+}
+
+// Destino dos eventos de um job
+pub trait ProgressSink: Send + Sync {
+    fn job_started(&self, _job_id: u64, _house: &str) {}
+
+    fn progress(&self, progress: &Progress);
+}
+
+impl<F: Fn(&Progress) + Send + Sync> ProgressSink for F {
+    fn progress(&self, progress: &Progress) {
+        self(progress)
+    }
+}
+
+struct TrackerState {
+    phase: Option<Phase>,
+    phase_started: Instant,
+    last_emit: Option<Instant>,
+}
+
+// Transforma os `ProgressStep` de um job em `Progress`, calculando o ETA
+// da fase atual e limitando a frequência dos eventos.
+pub struct ProgressTracker<'a> {
+    job_id: u64,
+    house: String,
+    sink: &'a dyn ProgressSink,
+    state: Mutex<TrackerState>,
+}
+
+impl<'a> ProgressTracker<'a> {
+    pub fn new(job_id: u64, house: &str, sink: &'a dyn ProgressSink) -> Self {
+        sink.job_started(job_id, house);
+
+        ProgressTracker {
+            job_id,
+            house: house.to_string(),
+            sink,
+            state: Mutex::new(TrackerState {
+                phase: None,
+                phase_started: Instant::now(),
+                last_emit: None,
+            }),
+        }
+    }
+
+    pub fn report(&self, step: ProgressStep) {
+        let mut state = self.state.lock().expect("Falha ao travar o progresso");
+        let now = Instant::now();
+
+        let phase_changed = state.phase != Some(step.phase);
+        if phase_changed {
+            state.phase = Some(step.phase);
+            state.phase_started = now;
+        }
+
+        // Sempre envia o início e o fim da fase
+        let finished = step.processed >= step.total;
+        let throttled = state
+            .last_emit
+            .is_some_and(|last| now.duration_since(last) < EMIT_INTERVAL);
+        if throttled && !phase_changed && !finished {
+            return;
+        }
+        state.last_emit = Some(now);
+
+        let eta_secs = if step.processed > 0 && !finished {
+            let elapsed = now.duration_since(state.phase_started).as_secs_f64();
+            Some(elapsed / step.processed as f64 * (step.total - step.processed) as f64)
+        } else {
+            None
+        };
+        drop(state);
+
+        self.sink.progress(&Progress {
+            job_id: self.job_id,
+            house: self.house.clone(),
+            phase: step.phase,
+            processed: step.processed,
+            total: step.total,
+            rejected: step.rejected,
+            eta_secs,
+        });
+    }
+}
+
+#[cfg(test)]
+mod testes {
+    use super::*;
+
+    #[test]
+    fn tracker_throttles_but_keeps_phase_edges() {
+        let events = Mutex::new(Vec::new());
+        let sink = |progress: &Progress| events.lock().unwrap().push(progress.clone());
+        let tracker = ProgressTracker::new(7, "Casa Teste", &sink);
+
+        for processed in 0..=1000 {
+            tracker.report(ProgressStep {
+                phase: Phase::Insert,
+                processed,
+                total: 1000,
+                rejected: 0,
+            });
+        }
+
+        let events = events.lock().unwrap();
+        assert!(events.len() < 1000);
+        assert_eq!(events.first().unwrap().processed, 0);
+        assert_eq!(events.last().unwrap().processed, 1000);
+        assert!(events.iter().all(|event| event.job_id == 7 && event.house == "Casa Teste"));
+    }
+}
@@ -0,0 +1,841 @@
+use super::db::BichoGroup;
+use super::migrations::APP_TABLES;
+use super::source::RawPage;
+use rusqlite::{params, Connection, Row};
+use serde::Serialize;
+
+// Sorteio pronto para ser gravado, com os valores como vieram do site
+#[derive(Debug, Clone)]
+pub struct NewDraw {
+    pub place: i32,
+    pub date: String, // aaaa-mm-dd
+    pub hour: String,
+    pub milhar: String,
+    pub dezena: String,
+    pub centena: String,
+    pub group: String,
+    pub scraped_group: Option<String>, // Grupo do site, só quando diverge do milhar
+}
+
+// Sorteio como está salvo na tabela `draws`
+#[derive(Debug, Clone)]
+pub struct StoredDraw {
+    pub id: i64,
+    pub place: u32,
+    pub date: i64,
+    pub hour: String,
+    pub milhar: String,
+    pub dezena: String,
+    pub centena: String,
+    pub group: String,
+    pub scraped_group: Option<String>,
+    pub updated_at: i64,
+}
+
+// O que o `DrawRepo::upsert` fez com o sorteio
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UpsertOutcome {
+    Inserted,
+    Updated,   // Já existia com outro milhar ou grupo
+    Unchanged, // Já existia igual
+}
+
+// Momento de corte das análises. Entram os sorteios de antes de `date` e, no
+// próprio dia, os até `hour` (HH:MM, inclusive). Sem `hour`, o dia inteiro.
+// `date` é a meia-noite UTC, como as datas dos sorteios são salvas.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cutoff {
+    pub date: i64,
+    pub hour: Option<String>,
+}
+
+// Sorteio como o `DrawRepo::history` devolve
+#[derive(Debug, Clone)]
+pub struct HistoryRow {
+    pub hour: String,
+    pub place: u32,
+    pub date: i64,
+    pub group: i64,
+}
+
+// Correção de um sorteio já salvo, registrada pelo `DrawRepo::upsert`
+#[derive(Serialize, Debug, Clone)]
+pub struct DrawRevision {
+    pub id: i64,
+    pub draw_id: i64,
+    pub house_id: String,
+    pub place: u32,
+    pub date: i64,
+    pub hour: String,
+    pub old_milhar: String,
+    pub new_milhar: String,
+    pub old_group: i64,
+    pub new_group: i64,
+    pub old_scraped_group: Option<String>,
+    pub new_scraped_group: Option<String>,
+    pub revised_at: i64,
+}
+
+// Resposta da fonte guardada no arquivo, sem o corpo
+#[derive(Serialize, Debug, Clone)]
+pub struct RawPayloadInfo {
+    pub id: i64,
+    pub house_id: String,
+    pub url: String,
+    pub fetched_at: i64,
+    pub sha256: String,
+    pub size: i64, // Tamanho sem compressão, em bytes
+}
+
+// Sorteio cujo grupo do site não bate com o do milhar
+#[derive(Serialize, Debug, Clone)]
+pub struct FlaggedDraw {
+    pub id: i64,
+    pub house_id: String,
+    pub place: u32,
+    pub date: i64,
+    pub hour: String,
+    pub milhar: String,
+    pub group: i64,
+    pub scraped_group: String,
+}
+
+// Dois últimos dígitos do milhar
+pub fn dezena_of(milhar: &str) -> String {
+    last_digits(milhar, 2)
+}
+
+// Três últimos dígitos do milhar
+pub fn centena_of(milhar: &str) -> String {
+    last_digits(milhar, 3)
+}
+
+// Grupo do bicho pela dezena: 01-04 é o 1, ..., 97-00 é o 25.
+// `None` se o milhar não for só de dígitos.
+pub fn group_of(milhar: &str) -> Option<u32> {
+    if milhar.len() < 2 || !milhar.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    match dezena_of(milhar).parse::<u32>().ok()? {
+        0 => Some(25),
+        dezena => Some(dezena.div_ceil(4)),
+    }
+}
+
+fn last_digits(milhar: &str, count: usize) -> String {
+    let chars: Vec<char> = milhar.chars().collect();
+
+    chars[chars.len().saturating_sub(count)..].iter().collect()
+}
+
+// Os grupos acompanhados ficam salvos como texto, separados por vírgula
+pub fn join_groups(groups: &[u32]) -> String {
+    groups
+        .iter()
+        .map(|num| num.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+pub fn split_groups(groups: &str) -> Vec<u32> {
+    groups
+        .split(',')
+        .filter_map(|s| s.trim().parse::<u32>().ok())
+        .collect()
+}
+
+// Acesso à tabela `draws`. Todas as consultas usam parâmetros e ficam no
+// cache de statements da conexão.
+pub struct DrawRepo<'c> {
+    conn: &'c Connection,
+}
+
+impl<'c> DrawRepo<'c> {
+    pub fn new(conn: &'c Connection) -> Self {
+        DrawRepo { conn }
+    }
+
+    // Grava o sorteio. Se ele já existir (mesma casa, prêmio, data e horário),
+    // só atualiza quando o milhar ou o grupo mudaram. Os marcadores "999" de
+    // milhar ou grupo ausente nunca substituem um valor de verdade já salvo.
+    // Um milhar ou grupo de verdade trocado por outro fica em `draw_revisions`;
+    // mudar só o grupo do site guardado à parte não é correção.
+    pub fn upsert(&self, lottery: &str, draw: &NewDraw) -> Result<UpsertOutcome, rusqlite::Error> {
+        let mut insert = self.conn.prepare_cached(
+            "INSERT INTO draws (house_id, place, date, hour, milhar, dezena, centena, \"group\", scraped_group, updated_at)
+             VALUES (?1, ?2, strftime('%s', ?3), ?4, ?5, ?6, ?7, ?8, ?9, strftime('%s', 'now'))
+             ON CONFLICT (house_id, place, date, hour) DO NOTHING",
+        )?;
+        let values = params![
+            lottery,
+            draw.place,
+            draw.date,
+            draw.hour,
+            draw.milhar,
+            draw.dezena,
+            draw.centena,
+            draw.group,
+            draw.scraped_group
+        ];
+        if insert.execute(values)? > 0 {
+            return Ok(UpsertOutcome::Inserted);
+        }
+
+        // Antes do UPDATE, enquanto a linha ainda tem os valores antigos
+        let mut revision = self.conn.prepare_cached(
+            "INSERT INTO draw_revisions (draw_id, house_id, old_milhar, new_milhar, old_group, new_group,
+                old_scraped_group, new_scraped_group)
+             SELECT id, house_id, milhar, ?5, \"group\", ?8, scraped_group, ?9 FROM draws
+             WHERE house_id = ?1 AND place = ?2 AND date = strftime('%s', ?3) AND hour = ?4
+                AND (?5 <> '999' OR milhar = '999')
+                AND ((milhar <> ?5 AND milhar <> '999' AND ?5 <> '999')
+                    OR (CAST(\"group\" AS TEXT) <> ?8 AND CAST(\"group\" AS TEXT) <> '999' AND ?8 <> '999'))",
+        )?;
+        revision.execute(values)?;
+
+        let mut update = self.conn.prepare_cached(
+            "UPDATE draws SET milhar = ?5, dezena = ?6, centena = ?7, \"group\" = ?8, scraped_group = ?9,
+                updated_at = strftime('%s', 'now')
+             WHERE house_id = ?1 AND place = ?2 AND date = strftime('%s', ?3) AND hour = ?4
+                AND (milhar IS NOT ?5 OR \"group\" IS NOT ?8 OR scraped_group IS NOT ?9)
+                AND (?5 <> '999' OR milhar = '999')
+                AND (?8 <> '999' OR CAST(\"group\" AS TEXT) = '999')",
+        )?;
+
+        Ok(match update.execute(values)? {
+            0 => UpsertOutcome::Unchanged,
+            _ => UpsertOutcome::Updated,
+        })
+    }
+
+    pub fn count(&self, lottery: &str) -> Result<i32, rusqlite::Error> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT count(*) FROM draws WHERE house_id = ?1")?;
+
+        stmt.query_row([lottery], |row| row.get(0))
+    }
+
+    pub fn all(&self, lottery: &str) -> Result<Vec<StoredDraw>, rusqlite::Error> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT id, place, date, hour, milhar, dezena, centena, CAST(\"group\" AS TEXT), scraped_group, updated_at
+             FROM draws WHERE house_id = ?1 ORDER BY id",
+        )?;
+
+        let draws = stmt
+            .query_map([lottery], |row| {
+                Ok(StoredDraw {
+                    id: row.get(0)?,
+                    place: row.get(1)?,
+                    date: row.get(2)?,
+                    hour: row.get(3)?,
+                    milhar: row.get(4)?,
+                    dezena: row.get(5)?,
+                    centena: row.get(6)?,
+                    group: row.get(7)?,
+                    scraped_group: row.get(8)?,
+                    updated_at: row.get(9)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(draws)
+    }
+
+    // Sorteios com o grupo do site divergente, de uma loteria ou de todas
+    pub fn flagged(&self, lottery: Option<&str>) -> Result<Vec<FlaggedDraw>, rusqlite::Error> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT id, house_id, place, date, hour, milhar, \"group\", scraped_group FROM draws
+             WHERE scraped_group IS NOT NULL AND (?1 IS NULL OR house_id = ?1)
+             ORDER BY house_id, date DESC, hour, place",
+        )?;
+
+        let draws = stmt
+            .query_map([lottery], |row| {
+                Ok(FlaggedDraw {
+                    id: row.get(0)?,
+                    house_id: row.get(1)?,
+                    place: row.get(2)?,
+                    date: row.get(3)?,
+                    hour: row.get(4)?,
+                    milhar: row.get(5)?,
+                    group: row.get(6)?,
+                    scraped_group: row.get(7)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(draws)
+    }
+
+    pub fn hours(&self, lottery: &str) -> Result<Vec<String>, rusqlite::Error> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT DISTINCT hour FROM draws WHERE house_id = ?1")?;
+        let hours = stmt
+            .query_map([lottery], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(hours)
+    }
+
+    pub fn places(&self, lottery: &str) -> Result<Vec<u32>, rusqlite::Error> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT DISTINCT place FROM draws WHERE house_id = ?1")?;
+        let places = stmt
+            .query_map([lottery], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(places)
+    }
+
+    // Todos os sorteios da loteria, em ordem de horário, prêmio e data, para
+    // as análises feitas em memória. Grupos que não são número viram 0.
+    // Com `as_of`, só vêm os sorteios até o corte.
+    pub fn history(&self, lottery: &str, as_of: Option<&Cutoff>) -> Result<Vec<HistoryRow>, rusqlite::Error> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT hour, place, date, CAST(\"group\" AS INTEGER) FROM draws
+             WHERE house_id = ?1 AND (?2 IS NULL OR date < ?2 OR (date = ?2 AND (?3 IS NULL OR hour <= ?3)))
+             ORDER BY hour, place, date",
+        )?;
+
+        let date = as_of.map(|cutoff| cutoff.date);
+        let hour = as_of.and_then(|cutoff| cutoff.hour.as_deref());
+        let rows = stmt
+            .query_map(params![lottery, date, hour], parse_history_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(rows)
+    }
+
+    // Como o `history`, mas só de um horário e prêmio
+    pub fn series(&self, lottery: &str, hour: &str, place: u32) -> Result<Vec<HistoryRow>, rusqlite::Error> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT hour, place, date, CAST(\"group\" AS INTEGER) FROM draws
+             WHERE house_id = ?1 AND hour = ?2 AND place = ?3 ORDER BY date",
+        )?;
+
+        let rows = stmt
+            .query_map(params![lottery, hour, place], parse_history_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(rows)
+    }
+}
+
+// Acesso à tabela `groups`
+pub struct GroupRepo<'c> {
+    conn: &'c Connection,
+}
+
+impl<'c> GroupRepo<'c> {
+    pub fn new(conn: &'c Connection) -> Self {
+        GroupRepo { conn }
+    }
+
+    pub fn list(&self, lottery: &str) -> Result<Vec<BichoGroup>, rusqlite::Error> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT id, hour, place, \"group\" FROM groups WHERE house_id = ?1 ORDER BY id",
+        )?;
+        let groups = stmt
+            .query_map([lottery], parse_row_to_bicho_group)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(groups)
+    }
+
+    pub fn insert(&self, lottery: &str, group: &BichoGroup) -> Result<usize, rusqlite::Error> {
+        let mut stmt = self.conn.prepare_cached(
+            "INSERT INTO groups (house_id, hour, place, \"group\", updated_at)
+             VALUES (?1, ?2, ?3, ?4, strftime('%s', 'now'))",
+        )?;
+
+        stmt.execute(params![lottery, group.hour, group.place, join_groups(&group.group)])
+    }
+
+    // Retorna quantas linhas foram alteradas, 0 se o id não é da loteria
+    pub fn update(&self, lottery: &str, id: u32, group: &BichoGroup) -> Result<usize, rusqlite::Error> {
+        let mut stmt = self.conn.prepare_cached(
+            "UPDATE groups SET hour = ?1, place = ?2, \"group\" = ?3, updated_at = strftime('%s', 'now')
+             WHERE id = ?4 AND house_id = ?5",
+        )?;
+
+        stmt.execute(params![group.hour, group.place, join_groups(&group.group), id, lottery])
+    }
+
+    pub fn delete(&self, lottery: &str, id: u32) -> Result<usize, rusqlite::Error> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("DELETE FROM groups WHERE id = ?1 AND house_id = ?2")?;
+
+        stmt.execute(params![id, lottery])
+    }
+}
+
+// Sorteio rejeitado, guardado com o JSON como veio do site
+#[derive(Serialize, Debug, Clone)]
+pub struct QuarantinedDraw {
+    pub id: i64,
+    pub house_id: String,
+    pub reason: String,
+    pub raw: String,
+    pub created_at: i64,
+}
+
+// Acesso à tabela `quarantine`
+pub struct QuarantineRepo<'c> {
+    conn: &'c Connection,
+}
+
+impl<'c> QuarantineRepo<'c> {
+    pub fn new(conn: &'c Connection) -> Self {
+        QuarantineRepo { conn }
+    }
+
+    // O mesmo sorteio rejeitado de novo só atualiza o motivo
+    pub fn insert(&self, lottery: &str, reason: &str, raw: &str) -> Result<usize, rusqlite::Error> {
+        let mut stmt = self.conn.prepare_cached(
+            "INSERT INTO quarantine (house_id, reason, raw) VALUES (?1, ?2, ?3)
+             ON CONFLICT (house_id, raw) DO UPDATE SET reason = excluded.reason",
+        )?;
+
+        stmt.execute(params![lottery, reason, raw])
+    }
+
+    // Sorteios rejeitados de uma loteria ou de todas
+    pub fn list(&self, lottery: Option<&str>) -> Result<Vec<QuarantinedDraw>, rusqlite::Error> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT id, house_id, reason, raw, created_at FROM quarantine
+             WHERE ?1 IS NULL OR house_id = ?1 ORDER BY house_id, id",
+        )?;
+
+        let draws = stmt
+            .query_map([lottery], |row| {
+                Ok(QuarantinedDraw {
+                    id: row.get(0)?,
+                    house_id: row.get(1)?,
+                    reason: row.get(2)?,
+                    raw: row.get(3)?,
+                    created_at: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(draws)
+    }
+
+    pub fn delete(&self, id: i64) -> Result<usize, rusqlite::Error> {
+        let mut stmt = self.conn.prepare_cached("DELETE FROM quarantine WHERE id = ?1")?;
+
+        stmt.execute([id])
+    }
+}
+
+// Leitura da tabela `draw_revisions`. Quem escreve nela é o `DrawRepo::upsert`.
+pub struct RevisionRepo<'c> {
+    conn: &'c Connection,
+}
+
+impl<'c> RevisionRepo<'c> {
+    pub fn new(conn: &'c Connection) -> Self {
+        RevisionRepo { conn }
+    }
+
+    pub fn last_id(&self) -> Result<i64, rusqlite::Error> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT coalesce(max(id), 0) FROM draw_revisions")?;
+
+        stmt.query_row([], |row| row.get(0))
+    }
+
+    // Correções com id maior que `after_id`, de uma loteria ou de todas, da mais nova para a mais velha
+    pub fn list(&self, lottery: Option<&str>, after_id: i64) -> Result<Vec<DrawRevision>, rusqlite::Error> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT r.id, r.draw_id, r.house_id, d.place, d.date, d.hour, r.old_milhar, r.new_milhar,
+                r.old_group, r.new_group, r.old_scraped_group, r.new_scraped_group, r.revised_at
+             FROM draw_revisions r JOIN draws d ON d.id = r.draw_id
+             WHERE r.id > ?1 AND (?2 IS NULL OR r.house_id = ?2)
+             ORDER BY r.id DESC",
+        )?;
+
+        let revisions = stmt
+            .query_map(params![after_id, lottery], |row| {
+                Ok(DrawRevision {
+                    id: row.get(0)?,
+                    draw_id: row.get(1)?,
+                    house_id: row.get(2)?,
+                    place: row.get(3)?,
+                    date: row.get(4)?,
+                    hour: row.get(5)?,
+                    old_milhar: row.get(6)?,
+                    new_milhar: row.get(7)?,
+                    old_group: row.get(8)?,
+                    new_group: row.get(9)?,
+                    old_scraped_group: row.get(10)?,
+                    new_scraped_group: row.get(11)?,
+                    revised_at: row.get(12)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(revisions)
+    }
+}
+
+// Acesso à tabela `raw_payloads`. O corpo já chega comprimido.
+pub struct RawPayloadRepo<'c> {
+    conn: &'c Connection,
+}
+
+impl<'c> RawPayloadRepo<'c> {
+    pub fn new(conn: &'c Connection) -> Self {
+        RawPayloadRepo { conn }
+    }
+
+    // A mesma resposta baixada de novo só atualiza a data e a URL
+    pub fn insert(
+        &self,
+        lottery: &str,
+        page: &RawPage,
+        sha256: &str,
+        compressed: &[u8],
+    ) -> Result<usize, rusqlite::Error> {
+        let mut stmt = self.conn.prepare_cached(
+            "INSERT INTO raw_payloads (house_id, url, fetched_at, sha256, size, body) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT (house_id, sha256) DO UPDATE SET url = excluded.url, fetched_at = excluded.fetched_at",
+        )?;
+
+        stmt.execute(params![
+            lottery,
+            page.url,
+            page.fetched_at,
+            sha256,
+            page.body.len() as i64,
+            compressed
+        ])
+    }
+
+    // Da resposta mais nova para a mais velha. Na mesma hora, na ordem em que foram baixadas.
+    pub fn list(&self, lottery: Option<&str>) -> Result<Vec<RawPayloadInfo>, rusqlite::Error> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT id, house_id, url, fetched_at, sha256, size FROM raw_payloads
+             WHERE ?1 IS NULL OR house_id = ?1
+             ORDER BY fetched_at DESC, id",
+        )?;
+
+        let payloads = stmt
+            .query_map([lottery], |row| {
+                Ok(RawPayloadInfo {
+                    id: row.get(0)?,
+                    house_id: row.get(1)?,
+                    url: row.get(2)?,
+                    fetched_at: row.get(3)?,
+                    sha256: row.get(4)?,
+                    size: row.get(5)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(payloads)
+    }
+
+    pub fn body(&self, id: i64) -> Result<Vec<u8>, rusqlite::Error> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT body FROM raw_payloads WHERE id = ?1")?;
+
+        stmt.query_row([id], |row| row.get(0))
+    }
+}
+
+fn parse_history_row(row: &Row) -> Result<HistoryRow, rusqlite::Error> {
+    Ok(HistoryRow {
+        hour: row.get(0)?,
+        place: row.get(1)?,
+        date: row.get(2)?,
+        group: row.get(3)?,
+    })
+}
+
+fn parse_row_to_bicho_group(row: &Row) -> Result<BichoGroup, rusqlite::Error> {
+    let id: u32 = row.get("id")?;
+    let hour: String = row.get("hour")?;
+    let place: u32 = row.get("place")?;
+    let group_str: String = row.get("group")?;
+
+    Ok(BichoGroup {
+        id: Some(id),
+        hour,
+        place,
+        group: split_groups(&group_str),
+    })
+}
+
+// Só deixa passar para um SQL montado com `format!` o nome de uma tabela
+// antiga de casa: que existe no banco, só tem letras (com acento também),
+// números e `_`, e não é do app nem do SQLite. Volta já entre aspas.
+pub fn checked_table(conn: &Connection, table_name: &str) -> Result<Option<String>, rusqlite::Error> {
+    let lowercase = table_name.to_ascii_lowercase();
+    let valid = !table_name.is_empty()
+        && table_name.chars().all(|c| c.is_alphanumeric() || c == '_')
+        && !lowercase.starts_with("sqlite_")
+        && !APP_TABLES.contains(&lowercase.as_str());
+    if !valid {
+        return Ok(None);
+    }
+
+    let exists: bool = conn.query_row(
+        "SELECT count(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        [table_name],
+        |row| row.get(0),
+    )?;
+
+    Ok(if exists { Some(format!("\"{}\"", table_name)) } else { None })
+}
+
+#[cfg(test)]
+mod testes {
+    use super::*;
+    use crate::modules::migrations;
+
+    fn test_db() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::migrate(&mut conn).unwrap();
+        conn.execute(
+            "INSERT INTO houses (value, name, position, first_seen, last_seen) VALUES ('zebra', 'A Zebra', 0, 0, 0)",
+            [],
+        )
+        .unwrap();
+
+        conn
+    }
+
+    #[test]
+    fn values_with_quotes_are_stored_as_is() {
+        let conn = test_db();
+        let draws = DrawRepo::new(&conn);
+        let draw = NewDraw {
+            place: 1,
+            date: "2024-02-01".to_string(),
+            hour: "14:00 'PT'".to_string(),
+            milhar: "0452".to_string(),
+            dezena: "52".to_string(),
+            centena: "452".to_string(),
+            group: "13".to_string(),
+            scraped_group: None,
+        };
+
+        assert_eq!(draws.upsert("zebra", &draw).unwrap(), UpsertOutcome::Inserted);
+        assert_eq!(draws.hours("zebra").unwrap(), vec!["14:00 'PT'".to_string()]);
+        let groups = GroupRepo::new(&conn);
+        for (hour, group) in [("14:00 'PT'", vec![2, 13]), ("18:00", vec![13])] {
+            let group = BichoGroup {
+                id: None,
+                hour: hour.to_string(),
+                place: 1,
+                group,
+            };
+            groups.insert("zebra", &group).unwrap();
+        }
+        let hours: Vec<String> = groups.list("zebra").unwrap().into_iter().map(|group| group.hour).collect();
+        assert_eq!(hours, vec!["14:00 'PT'".to_string(), "18:00".to_string()]);
+        assert_eq!(draws.history("zebra", None).unwrap()[0].hour, "14:00 'PT'");
+        assert_eq!(draws.all("zebra").unwrap()[0].milhar, "0452");
+    }
+
+    #[test]
+    fn upsert_tells_inserts_updates_and_repeats_apart() {
+        let conn = test_db();
+        let draws = DrawRepo::new(&conn);
+        let mut draw = NewDraw {
+            place: 1,
+            date: "2024-02-01".to_string(),
+            hour: "14:00".to_string(),
+            milhar: "0452".to_string(),
+            dezena: "52".to_string(),
+            centena: "452".to_string(),
+            group: "13".to_string(),
+            scraped_group: None,
+        };
+
+        assert_eq!(draws.upsert("zebra", &draw).unwrap(), UpsertOutcome::Inserted);
+        assert_eq!(draws.upsert("zebra", &draw).unwrap(), UpsertOutcome::Unchanged);
+
+        draw.milhar = "0453".to_string();
+        assert_eq!(draws.upsert("zebra", &draw).unwrap(), UpsertOutcome::Updated);
+        assert_eq!(draws.count("zebra").unwrap(), 1);
+        assert_eq!(draws.all("zebra").unwrap()[0].milhar, "0453");
+
+        draw.place = 2;
+        assert_eq!(draws.upsert("zebra", &draw).unwrap(), UpsertOutcome::Inserted);
+
+        // Só a mudança do milhar ficou no histórico
+        let revisions = RevisionRepo::new(&conn).list(Some("zebra"), 0).unwrap();
+        assert_eq!(revisions.len(), 1);
+        assert_eq!(
+            (revisions[0].old_milhar.as_str(), revisions[0].new_milhar.as_str()),
+            ("0452", "0453")
+        );
+        assert!(RevisionRepo::new(&conn).list(None, revisions[0].id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn placeholders_never_replace_real_values() {
+        let conn = test_db();
+        let draws = DrawRepo::new(&conn);
+        let real = NewDraw {
+            place: 1,
+            date: "2024-02-01".to_string(),
+            hour: "14:00".to_string(),
+            milhar: "0452".to_string(),
+            dezena: "52".to_string(),
+            centena: "452".to_string(),
+            group: "13".to_string(),
+            scraped_group: Some("9".to_string()),
+        };
+        // Sorteio do mesmo dia e horário vindo sem milhar, com ou sem o grupo do site
+        let placeholder = |place: i32, group: &str| NewDraw {
+            place,
+            milhar: "999".to_string(),
+            dezena: String::new(),
+            centena: String::new(),
+            group: group.to_string(),
+            scraped_group: None,
+            ..real.clone()
+        };
+
+        assert_eq!(draws.upsert("zebra", &real).unwrap(), UpsertOutcome::Inserted);
+        assert_eq!(draws.upsert("zebra", &placeholder(1, "999")).unwrap(), UpsertOutcome::Unchanged);
+        assert_eq!(draws.upsert("zebra", &placeholder(1, "7")).unwrap(), UpsertOutcome::Unchanged);
+        let stored = &draws.all("zebra").unwrap()[0];
+        assert_eq!(
+            (stored.milhar.as_str(), stored.dezena.as_str(), stored.group.as_str(), stored.scraped_group.as_deref()),
+            ("0452", "52", "13", Some("9"))
+        );
+
+        // Um marcador dá lugar ao grupo que chegou depois, mas não o contrário
+        assert_eq!(draws.upsert("zebra", &placeholder(2, "999")).unwrap(), UpsertOutcome::Inserted);
+        assert_eq!(draws.upsert("zebra", &placeholder(2, "7")).unwrap(), UpsertOutcome::Updated);
+        assert_eq!(draws.upsert("zebra", &placeholder(2, "999")).unwrap(), UpsertOutcome::Unchanged);
+        assert_eq!(draws.all("zebra").unwrap()[1].group, "7");
+    }
+
+    #[test]
+    fn only_real_values_replaced_by_real_values_are_revisions() {
+        let conn = test_db();
+        let draws = DrawRepo::new(&conn);
+        let mut draw = NewDraw {
+            place: 1,
+            date: "2024-02-01".to_string(),
+            hour: "14:00".to_string(),
+            milhar: "999".to_string(),
+            dezena: String::new(),
+            centena: String::new(),
+            group: "999".to_string(),
+            scraped_group: None,
+        };
+        let revisions = || RevisionRepo::new(&conn).list(Some("zebra"), 0).unwrap();
+
+        // O milhar que faltava chegou: não é correção
+        draws.upsert("zebra", &draw).unwrap();
+        draw.milhar = "0452".to_string();
+        draw.group = "13".to_string();
+        assert_eq!(draws.upsert("zebra", &draw).unwrap(), UpsertOutcome::Updated);
+        assert!(revisions().is_empty());
+
+        // Só o grupo do site mudou, como num reprocessamento: também não
+        draw.scraped_group = Some("9".to_string());
+        assert_eq!(draws.upsert("zebra", &draw).unwrap(), UpsertOutcome::Updated);
+        assert!(revisions().is_empty());
+
+        // O site trocou o milhar por outro
+        draw.milhar = "0461".to_string();
+        draw.group = "16".to_string();
+        assert_eq!(draws.upsert("zebra", &draw).unwrap(), UpsertOutcome::Updated);
+        let revisions = revisions();
+        assert_eq!(revisions.len(), 1);
+        assert_eq!((revisions[0].old_group, revisions[0].new_group), (13, 16));
+    }
+
+    #[test]
+    fn history_stops_at_the_cutoff() {
+        let conn = test_db();
+        conn.execute_batch(
+            "INSERT INTO draws (house_id, place, date, hour, milhar, dezena, centena, \"group\") VALUES
+                ('zebra', 1, 1, '14:00', '0018', '18', '018', 5),
+                ('zebra', 1, 2, '14:00', '0034', '34', '034', 9),
+                ('zebra', 1, 3, '18:00', '0018', '18', '018', 5),
+                ('zebra', 1, 4, '14:00', '0046', '46', '046', 12),
+                ('zebra', 2, 5, '14:00', '0018', '18', '018', 5),
+                ('zebra', 1, 6, '14:00', '0010', '10', '010', 3);",
+        )
+        .unwrap();
+
+        let dates = |as_of: Option<Cutoff>| -> Vec<i64> {
+            DrawRepo::new(&conn)
+                .history("zebra", as_of.as_ref())
+                .unwrap()
+                .into_iter()
+                .map(|row| row.date)
+                .collect()
+        };
+        // Em ordem de horário, prêmio e data
+        assert_eq!(dates(None), vec![1, 2, 4, 6, 5, 3]);
+
+        // Como estava na data 4: os sorteios das datas 5 e 6 ainda não tinham saído
+        let cutoff = |date, hour: Option<&str>| Some(Cutoff { date, hour: hour.map(String::from) });
+        assert_eq!(dates(cutoff(4, None)), vec![1, 2, 4, 3]);
+
+        // Às 13:00 da data 2, o sorteio das 14:00 ainda não tinha saído
+        assert_eq!(dates(cutoff(2, Some("13:00"))), vec![1]);
+        assert_eq!(dates(cutoff(2, Some("14:00"))), vec![1, 2]);
+    }
+
+    #[test]
+    fn dezena_and_centena_are_the_last_digits() {
+        assert_eq!((dezena_of("0452"), centena_of("0452")), ("52".to_string(), "452".to_string()));
+        assert_eq!((dezena_of("7"), centena_of("7")), ("7".to_string(), "7".to_string()));
+    }
+
+    #[test]
+    fn group_comes_from_the_dezena() {
+        assert_eq!(group_of("0401"), Some(1));
+        assert_eq!(group_of("1204"), Some(1));
+        assert_eq!(group_of("0452"), Some(13));
+        assert_eq!(group_of("3397"), Some(25));
+        assert_eq!(group_of("5000"), Some(25));
+        assert_eq!(group_of("12a4"), None);
+    }
+
+    #[test]
+    fn only_existing_legacy_tables_are_allowed() {
+        let conn = test_db();
+        conn.execute("CREATE TABLE A_Zebra (id INTEGER PRIMARY KEY)", []).unwrap();
+        conn.execute("CREATE TABLE Look_Goiás (id INTEGER PRIMARY KEY)", []).unwrap();
+
+        assert_eq!(checked_table(&conn, "A_Zebra").unwrap(), Some("\"A_Zebra\"".to_string()));
+        // Casas com acento no nome tinham a tabela com acento também
+        assert_eq!(checked_table(&conn, "Look_Goiás").unwrap(), Some("\"Look_Goiás\"".to_string()));
+        assert_eq!(checked_table(&conn, "Look_Goiás\"; --").unwrap(), None);
+        assert_eq!(checked_table(&conn, "A_Tigre").unwrap(), None);
+        assert_eq!(checked_table(&conn, "draws; DROP TABLE houses").unwrap(), None);
+        assert_eq!(checked_table(&conn, "DRAWS").unwrap(), None);
+
+        // Nenhuma tabela do app passa, nem as que vierem em migrações novas
+        let mut stmt = conn
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT IN ('A_Zebra', 'Look_Goiás')")
+            .unwrap();
+        let tables: Vec<String> = stmt
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert!(!tables.is_empty());
+        for table in tables {
+            assert_eq!(checked_table(&conn, &table).unwrap(), None, "{}", table);
+        }
+    }
+}
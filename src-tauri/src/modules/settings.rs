@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const SETTINGS_FILE: &str = "settings.json";
+
+// Configurações salvas em `settings.json`, na pasta de configuração do app.
+// Campos ausentes no arquivo ficam com o padrão.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Settings {
+    // Caminho do banco. Se for relativo, parte da pasta de dados do app.
+    pub db_path: Option<PathBuf>,
+    // Workspace aberto por último. Sem ele, usa o banco principal.
+    pub workspace: Option<String>,
+    // Rejeita sorteios com campos faltando em vez de gravar "999"
+    pub strict_ingestion: bool,
+}
+
+pub fn settings_path(config_dir: &Path) -> PathBuf {
+    config_dir.join(SETTINGS_FILE)
+}
+
+// Lê as configurações. Sem o arquivo, usa os padrões.
+pub fn load(config_dir: &Path) -> Result<Settings, String> {
+    let path = settings_path(config_dir);
+    if !path.exists() {
+        return Ok(Settings::default());
+    }
+
+    let body = fs::read_to_string(&path).map_err(|err| err.to_string())?;
+    serde_json::from_str(&body)
+        .map_err(|err| format!("Erro ao ler as configurações em {}: {}", path.display(), err))
+}
+
+pub fn save(config_dir: &Path, settings: &Settings) -> Result<(), String> {
+    fs::create_dir_all(config_dir).map_err(|err| err.to_string())?;
+    let body = serde_json::to_string_pretty(settings).map_err(|err| err.to_string())?;
+
+    fs::write(settings_path(config_dir), body).map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod testes {
+    use super::*;
+
+    #[test]
+    fn missing_file_or_fields_use_defaults() {
+        let dir = std::env::temp_dir().join(format!("get_bicho_settings_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(load(&dir).unwrap().db_path.is_none());
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(settings_path(&dir), "{}").unwrap();
+        assert!(load(&dir).unwrap().db_path.is_none());
+
+        let settings = Settings {
+            db_path: Some(PathBuf::from("outro.db")),
+            ..Settings::default()
+        };
+        save(&dir, &settings).unwrap();
+        assert_eq!(load(&dir).unwrap().db_path, Some(PathBuf::from("outro.db")));
+    }
+}
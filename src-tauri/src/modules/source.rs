@@ -0,0 +1,184 @@
+use super::db::BichoData;
+use super::web::{self, House};
+use async_trait::async_trait;
+use chrono::{NaiveDate, Utc};
+use std::error::Error;
+use std::path::PathBuf;
+
+pub type SourceError = Box<dyn Error + Send + Sync>;
+
+// Resposta da fonte como veio, guardada no arquivo de payloads
+#[derive(Debug, Clone)]
+pub struct RawPage {
+    pub url: String,
+    pub fetched_at: i64,
+    pub body: String,
+}
+
+// Sorteios baixados: o JSON já juntado e as páginas de onde ele saiu
+#[derive(Debug, Clone)]
+pub struct FetchedDraws {
+    pub json: String,
+    pub pages: Vec<RawPage>,
+}
+
+// Site (ou arquivo) de onde vêm as casas e os resultados
+#[async_trait]
+pub trait ResultSource: Send + Sync {
+    async fn list_houses(&self) -> Result<Vec<House>, SourceError>;
+
+    // Se a lista de casas vai para o banco e serve de cache. Só a fonte
+    // padrão: a lista de outra fonte não pode ser servida no lugar da dela.
+    fn caches_houses(&self) -> bool {
+        true
+    }
+
+    // Retorna o JSON dos sorteios entre `from` e `to` (inclusive) e as páginas baixadas.
+    // `on_page` recebe quantos dias do período já foram baixados e o total.
+    async fn fetch_draws(
+        &self,
+        lottery: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+        on_page: &(dyn Fn(usize, usize) + Send + Sync),
+    ) -> Result<FetchedDraws, SourceError>;
+}
+
+// Adaptador para o bicho365.com
+pub struct Bicho365;
+
+#[async_trait]
+impl ResultSource for Bicho365 {
+    async fn list_houses(&self) -> Result<Vec<House>, SourceError> {
+        web::get_houses().await
+    }
+
+    async fn fetch_draws(
+        &self,
+        lottery: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+        on_page: &(dyn Fn(usize, usize) + Send + Sync),
+    ) -> Result<FetchedDraws, SourceError> {
+        web::get_bichos_data(lottery.to_string(), from, to, on_page).await
+    }
+}
+
+// Adaptador que lê de uma pasta local, usado para testar sem internet.
+// A pasta deve conter `houses.json` e `draws/<lottery>.json`.
+pub struct FixtureSource {
+    dir: PathBuf,
+}
+
+impl FixtureSource {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        FixtureSource { dir: dir.into() }
+    }
+}
+
+#[async_trait]
+impl ResultSource for FixtureSource {
+    async fn list_houses(&self) -> Result<Vec<House>, SourceError> {
+        let body = tokio::fs::read_to_string(self.dir.join("houses.json")).await?;
+        let houses: Vec<House> = serde_json::from_str(&body)?;
+
+        Ok(houses)
+    }
+
+    fn caches_houses(&self) -> bool {
+        false
+    }
+
+    async fn fetch_draws(
+        &self,
+        lottery: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+        on_page: &(dyn Fn(usize, usize) + Send + Sync),
+    ) -> Result<FetchedDraws, SourceError> {
+        if from > to {
+            return Err("não há atualizações pendentes".into());
+        }
+
+        let path = self.dir.join("draws").join(format!("{}.json", lottery));
+        let body = tokio::fs::read_to_string(&path).await?;
+        let mut data: BichoData = serde_json::from_str(&body)?;
+        data.retain_between(from, to);
+        let total_days = (to - from).num_days() as usize + 1;
+        on_page(total_days, total_days);
+
+        // Só o período pedido vai para o arquivo, não o arquivo de fixture inteiro
+        let json = serde_json::to_string(&data)?;
+        Ok(FetchedDraws {
+            pages: vec![RawPage {
+                url: format!("file://{}", path.display()),
+                fetched_at: Utc::now().timestamp(),
+                body: json.clone(),
+            }],
+            json,
+        })
+    }
+}
+
+// Escolhe a fonte pelo nome: `bicho365` (padrão) ou `fixture:<pasta>`
+pub fn select_source(name: Option<&str>) -> Result<Box<dyn ResultSource>, String> {
+    match name {
+        None | Some("") | Some("bicho365") => Ok(Box::new(Bicho365)),
+        Some(name) => match name.strip_prefix("fixture:") {
+            Some(dir) if !dir.is_empty() => Ok(Box::new(FixtureSource::new(dir))),
+            _ => Err(format!("Fonte de resultados desconhecida: {}", name)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod testes {
+    use super::*;
+    use std::fs;
+
+    fn fixture_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("get_bicho_fixture_{}", std::process::id()));
+        fs::create_dir_all(dir.join("draws")).unwrap();
+        fs::write(
+            dir.join("houses.json"),
+            r#"[{"name": "A Zebra", "value": "zebra"}]"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("draws").join("zebra.json"),
+            r#"{"bicho_lotteries_draws": [[
+                {"place": "1", "lottery": {"title": null}, "thousand": "0452", "hour": "14:00", "group": "13", "date": "01/02/2024"},
+                {"place": "1", "lottery": {"title": null}, "thousand": "1234", "hour": "14:00", "group": "9", "date": "05/02/2024"}
+            ]], "show_more": false, "status": "ok"}"#,
+        )
+        .unwrap();
+
+        dir
+    }
+
+    #[tokio::test]
+    async fn fixture_source_filters_by_date() {
+        let source = select_source(Some(&format!("fixture:{}", fixture_dir().display()))).unwrap();
+
+        let houses = source.list_houses().await.unwrap();
+        assert_eq!(houses[0].value, "zebra");
+        // A lista do arquivo não substitui a do site salva no banco
+        assert!(!source.caches_houses());
+        assert!(select_source(None).unwrap().caches_houses());
+
+        let from = NaiveDate::from_ymd_opt(2024, 2, 2).unwrap();
+        let to = NaiveDate::from_ymd_opt(2024, 2, 10).unwrap();
+        let fetched = source.fetch_draws("zebra", from, to, &|_, _| {}).await.unwrap();
+        let data: BichoData = serde_json::from_str(&fetched.json).unwrap();
+        assert_eq!(data.total_draws(), 1);
+        assert_eq!(fetched.pages.len(), 1);
+        let archived: BichoData = serde_json::from_str(&fetched.pages[0].body).unwrap();
+        assert_eq!(archived.total_draws(), 1);
+    }
+
+    #[test]
+    fn unknown_source_is_rejected() {
+        assert!(select_source(Some("outro-site")).is_err());
+        assert!(select_source(Some("fixture:")).is_err());
+    }
+}
@@ -0,0 +1,317 @@
+use super::archive;
+use super::db::{self, SaveReport};
+use super::jobs::{self, JobHandle};
+use super::progress::{Phase, ProgressSink, ProgressStep, ProgressTracker};
+use super::repo::DrawRevision;
+use super::source::ResultSource;
+use super::web::House;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+// Máximo de dias baixados quando a casa ainda não tem sorteios salvos
+pub const MAX_BACKFILL_DAYS: i64 = 1600;
+
+// Quantas casas sincronizam ao mesmo tempo por padrão
+pub const SYNC_CONCURRENCY: usize = 4;
+
+// Por quanto tempo a lista de casas salva é usada sem consultar a fonte
+const HOUSES_CACHE_SECS: i64 = 60 * 60 * 3; // 3 horas
+
+#[derive(Serialize, Debug)]
+pub struct SyncSummary {
+    pub job_id: u64,
+    pub house: String,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub inserted: usize,
+    pub updated: usize,    // Sorteios que mudaram no site desde a última vez
+    pub duplicates: usize, // Sorteios já salvos iguais
+    pub skipped: usize,    // Sorteios inválidos
+    pub flagged: usize, // Sorteios salvos com o grupo do site divergente
+    pub rejected: usize, // Sorteios mandados para a quarentena no modo estrito
+    pub revisions: Vec<DrawRevision>, // Sorteios que o site corrigiu
+}
+
+// Resultado de uma casa dentro do `sync_all_houses`. Com erro, os contadores ficam zerados.
+#[derive(Serialize, Debug)]
+pub struct HouseSyncResult {
+    pub house: String,
+    pub lottery: String,
+    pub inserted: usize,
+    pub updated: usize,
+    pub duplicates: usize,
+    pub skipped: usize,
+    pub rejected: usize,
+    pub revisions: Vec<DrawRevision>,
+    pub error: Option<String>,
+}
+
+// Lista de casas salva no banco, atualizada pela fonte quando está velha.
+// Se a fonte falhar, usa a última lista salva. Fontes sem cache (fixture)
+// são lidas direto, sem passar pelo banco.
+pub async fn load_houses(source: &dyn ResultSource) -> Result<Vec<House>, String> {
+    if !source.caches_houses() {
+        return source.list_houses().await.map_err(|err| err.to_string());
+    }
+
+    let updated_at = db::houses_updated_at().map_err(|err| err.to_string())?;
+    let fresh = updated_at.is_some_and(|ts| Utc::now().timestamp() - ts < HOUSES_CACHE_SECS);
+
+    if !fresh {
+        match source.list_houses().await {
+            Ok(houses) => db::save_houses(&houses).map_err(|err| err.to_string())?,
+            Err(err) => {
+                println!("Erro ao pegar as casas, usando a lista salva: {:?}", err);
+                let saved = db::get_saved_houses().map_err(|err| err.to_string())?;
+                if saved.is_empty() {
+                    return Err(err.to_string());
+                }
+
+                return Ok(saved);
+            }
+        }
+    }
+
+    if let Err(err) = db::adopt_legacy_tables() {
+        println!("Erro ao migrar as tabelas antigas: {:?}", err);
+    }
+
+    db::get_saved_houses().map_err(|err| err.to_string())
+}
+
+// Período que falta baixar. O dia do último sorteio salvo é baixado de novo
+// porque os horários seguintes daquele dia podem ainda não ter saído.
+pub fn missing_range(
+    last_draw_date: Option<i64>,
+    today: NaiveDate,
+    full: bool,
+) -> (NaiveDate, NaiveDate) {
+    let oldest = today - Duration::days(MAX_BACKFILL_DAYS - 1);
+    let from = match last_draw_date.and_then(|ts| DateTime::from_timestamp(ts, 0)) {
+        Some(last) if !full => last.date_naive().clamp(oldest, today),
+        _ => oldest,
+    };
+
+    (from, today)
+}
+
+async fn fetch_and_save(
+    source: &dyn ResultSource,
+    house_name: &str,
+    lottery: &str,
+    from: NaiveDate,
+    to: NaiveDate,
+    job: &JobHandle,
+    tracker: &ProgressTracker<'_>,
+) -> Result<SyncSummary, String> {
+    let on_page = |processed: usize, total: usize| {
+        tracker.report(ProgressStep {
+            phase: Phase::Download,
+            processed,
+            total,
+            rejected: 0,
+        })
+    };
+
+    let fetched = tokio::select! {
+        fetched = source.fetch_draws(lottery, from, to, &on_page) => fetched.map_err(|err| err.to_string())?,
+        _ = job.cancelled() => return Err("Sincronização cancelada".to_string()),
+    };
+
+    // A gravação no SQLite é bloqueante, então libera a thread do runtime
+    let report: SaveReport = tokio::task::block_in_place(|| {
+        // As respostas ficam guardadas mesmo que a gravação falhe ou seja cancelada
+        archive::store_pages(lottery, &fetched.pages)?;
+
+        db::save_bicho_data(lottery.to_string(), &fetched.json, true, job.cancel_flag(), |step| {
+            tracker.report(step)
+        })
+        .map_err(|err| err.to_string())
+    })?;
+
+    Ok(SyncSummary {
+        job_id: job.id,
+        house: house_name.to_string(),
+        from,
+        to,
+        inserted: report.inserted,
+        updated: report.updated,
+        duplicates: report.duplicates,
+        skipped: report.skipped,
+        flagged: report.flagged,
+        rejected: report.rejected,
+        revisions: report.revisions,
+    })
+}
+
+// Baixa e salva os sorteios de `from` até `to` como um job cancelável
+pub async fn sync_range(
+    source: &dyn ResultSource,
+    house_name: &str,
+    lottery: &str,
+    from: NaiveDate,
+    to: NaiveDate,
+    sink: &dyn ProgressSink,
+) -> Result<SyncSummary, String> {
+    let job = jobs::start_job(house_name);
+    let tracker = ProgressTracker::new(job.id, house_name, sink);
+    let result = fetch_and_save(source, house_name, lottery, from, to, &job, &tracker).await;
+    jobs::finish_job(&job, &result);
+
+    result
+}
+
+// Resultado de um `reprocess_raw`
+#[derive(Serialize, Debug)]
+pub struct ReprocessSummary {
+    pub job_id: u64,
+    pub house: String,
+    pub payloads: usize, // Respostas do arquivo usadas
+    #[serde(flatten)]
+    pub report: SaveReport,
+}
+
+// Grava de novo os sorteios a partir das respostas guardadas, sem baixar nada.
+// Serve para aplicar correções do parser aos dados antigos.
+pub async fn reprocess_raw(
+    house_name: &str,
+    lottery: &str,
+    ids: Option<Vec<i64>>,
+    sink: &dyn ProgressSink,
+) -> Result<ReprocessSummary, String> {
+    let job = jobs::start_job(house_name);
+    let tracker = ProgressTracker::new(job.id, house_name, sink);
+    let result = tokio::task::block_in_place(|| {
+        let (payloads, json) = archive::merged_payloads(lottery, ids.as_deref())?;
+        // Nada foi baixado, então não conta como sincronização
+        let report = db::save_bicho_data(lottery.to_string(), &json, false, job.cancel_flag(), |step| {
+            tracker.report(step)
+        })
+        .map_err(|err| err.to_string())?;
+
+        Ok(ReprocessSummary {
+            job_id: job.id,
+            house: house_name.to_string(),
+            payloads,
+            report,
+        })
+    });
+    jobs::finish_job(&job, &result);
+
+    result
+}
+
+// Sincroniza apenas o que falta, a partir do estado salvo da casa
+pub async fn sync_house(
+    source: &dyn ResultSource,
+    house_name: &str,
+    lottery: &str,
+    full: bool,
+    sink: &dyn ProgressSink,
+) -> Result<SyncSummary, String> {
+    let job = jobs::start_job(house_name);
+    let tracker = ProgressTracker::new(job.id, house_name, sink);
+    let result = async {
+        let state = db::get_sync_state(lottery).map_err(|err| err.to_string())?;
+        let (from, to) = missing_range(state.last_draw_date, Utc::now().date_naive(), full);
+
+        fetch_and_save(source, house_name, lottery, from, to, &job, &tracker).await
+    }
+    .await;
+    jobs::finish_job(&job, &result);
+
+    result
+}
+
+// Sincroniza todas as casas da fonte, no máximo `concurrency` por vez.
+// Uma casa com erro não interrompe as outras.
+pub async fn sync_all_houses(
+    source: Arc<dyn ResultSource>,
+    full: bool,
+    concurrency: usize,
+    sink: Arc<dyn ProgressSink>,
+) -> Result<Vec<HouseSyncResult>, String> {
+    let houses = load_houses(source.as_ref()).await?;
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+    let mut tasks = JoinSet::new();
+
+    let active_houses = houses.into_iter().filter(|house| !house.disappeared);
+    for (index, house) in active_houses.enumerate() {
+        let source = source.clone();
+        let semaphore = semaphore.clone();
+        let sink = sink.clone();
+
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await.expect("Semáforo fechado");
+            let result =
+                sync_house(source.as_ref(), &house.name, &house.value, full, sink.as_ref()).await;
+
+            let result = match result {
+                Ok(summary) => HouseSyncResult {
+                    house: house.name,
+                    lottery: house.value,
+                    inserted: summary.inserted,
+                    updated: summary.updated,
+                    duplicates: summary.duplicates,
+                    skipped: summary.skipped,
+                    rejected: summary.rejected,
+                    revisions: summary.revisions,
+                    error: None,
+                },
+                Err(err) => HouseSyncResult {
+                    house: house.name,
+                    lottery: house.value,
+                    inserted: 0,
+                    updated: 0,
+                    duplicates: 0,
+                    skipped: 0,
+                    rejected: 0,
+                    revisions: Vec::new(),
+                    error: Some(err),
+                },
+            };
+
+            (index, result)
+        });
+    }
+
+    let mut results = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok(result) => results.push(result),
+            Err(err) => println!("Erro na tarefa de sincronização: {:?}", err),
+        }
+    }
+
+    // Mantém a ordem das casas do site
+    results.sort_by_key(|(index, _)| *index);
+
+    Ok(results.into_iter().map(|(_, result)| result).collect())
+}
+
+#[cfg(test)]
+mod testes {
+    use super::*;
+
+    #[test]
+    fn missing_range_starts_at_last_draw() {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+        let last = NaiveDate::from_ymd_opt(2024, 3, 7).unwrap();
+        let ts = last.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp();
+
+        assert_eq!(missing_range(Some(ts), today, false), (last, today));
+    }
+
+    #[test]
+    fn missing_range_backfills_empty_or_full_sync() {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+        let oldest = today - Duration::days(MAX_BACKFILL_DAYS - 1);
+
+        assert_eq!(missing_range(None, today, false), (oldest, today));
+        assert_eq!(missing_range(Some(0), today, false), (oldest, today));
+        assert_eq!(missing_range(Some(0), today, true), (oldest, today));
+    }
+}
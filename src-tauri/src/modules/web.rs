@@ -1,37 +1,23 @@
-use chrono::Utc;
-use lazy_static::lazy_static;
-use reqwest;
+use super::db::BichoData;
+use super::http::CLIENT;
+use super::source::{FetchedDraws, RawPage};
+use chrono::{NaiveDate, Utc};
 use scraper::{Html, Selector};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::error::Error;
-use std::time::{Duration, Instant};
-use tokio::sync::Mutex;
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct House {
     pub name: String,
     pub value: String,
+    #[serde(default)]
+    pub disappeared: bool, // Saiu da lista do site, mas ainda tem dados salvos
 }
 
-lazy_static! {
-    static ref CACHE: Mutex<Option<(Vec<House>, Instant)>> = Mutex::new(None);
-}
-
-const CACHE_DURATION: Duration = Duration::from_secs(60 * 60 * 3); // 5 minutos
-
-pub async fn get_houses() -> Result<Vec<House>, Box<dyn Error>> {
-    // Verifica o cache
-    let mut cache = CACHE.lock().await;
-    if let Some((cached_houses, timestamp)) = &*cache {
-        if timestamp.elapsed() < CACHE_DURATION {
-            // Retorna o cache se ainda estiver válido
-            return Ok(cached_houses.clone());
-        }
-    }
-
-    // Realiza a requisição HTTP e coleta os dados se o cache expirou
+pub async fn get_houses() -> Result<Vec<House>, Box<dyn Error + Send + Sync>> {
+    // Realiza a requisição HTTP e coleta as casas da página
     let url = "https://bicho365.com/deu-no-poste";
-    let body = reqwest::get(url).await?.text().await?;
+    let body = CLIENT.get_text(url).await?;
     let document = Html::parse_document(&body);
     let select_selector = Selector::parse("select[onchange]").unwrap();
 
@@ -49,29 +35,127 @@ pub async fn get_houses() -> Result<Vec<House>, Box<dyn Error>> {
             houses.push(House {
                 name: clean_text.to_string(),
                 value: value.to_string(),
+                disappeared: false,
             });
         }
     }
 
-    // Atualiza o cache com os dados novos
-    *cache = Some((houses.clone(), Instant::now()));
+    // Uma página sem casas indica que o layout mudou, não que todas sumiram
+    if houses.is_empty() {
+        return Err("nenhuma casa encontrada na página".into());
+    }
+
     Ok(houses)
 }
 
-pub async fn get_bichos_data(lottery: String, total_days: i32) -> Result<String, Box<dyn Error>> {
-    println!("a: {}", total_days);
-    if total_days == 0 {
-        println!("sem dados a pegar");
-        return Err("não há atualizações pendentes".into());
-    }
-    let current_date = Utc::now().format("%Y-%m-%d").to_string();
-    let url = format!(
+// Quantidade de dias pedida em cada página do arquivo de resultados
+const PAGE_DAYS: i32 = 30;
+
+// Limite de páginas por download. O backfill inteiro cabe em umas 55.
+const MAX_PAGES: usize = 200;
+
+fn archive_url(lottery: &str, end_date: NaiveDate, display: i32) -> String {
+    format!(
         "https://bicho365.com/wp-content/themes/os-bicho365-child/ajax/lottery-results-archive.php?wp_site_id=1&wp_post_id=323&data%5Bfields%5D%5Blottery%5D={}&data%5Bfields%5D%5Bdraw_type%5D=&data%5Bfields%5D%5Bdatetime%5D={}&data%5Bdisplay%5D%5B%5D={}&data%5Bdisplay%5D%5B%5D=10",
         lottery,
-        current_date,
-        100 * total_days
-    );
+        end_date.format("%Y-%m-%d"),
+        display
+    )
+}
+
+// Baixa os sorteios de `from` até `to`, página por página, enquanto o site
+// responder `show_more`. Cada página começa na data mais antiga da anterior.
+// As páginas voltam junto, como vieram, para o arquivo de payloads.
+pub async fn get_bichos_data(
+    lottery: String,
+    from: NaiveDate,
+    to: NaiveDate,
+    on_page: &(dyn Fn(usize, usize) + Send + Sync),
+) -> Result<FetchedDraws, Box<dyn Error + Send + Sync>> {
+    let total_days = (to - from).num_days() as i32 + 1;
+    if total_days <= 0 {
+        return Err("não há atualizações pendentes".into());
+    }
+
+    let mut merged: Option<BichoData> = None;
+    let mut pages: Vec<RawPage> = Vec::new();
+    let mut cursor = to;
+    loop {
+        let days_left = (cursor - from).num_days() as i32 + 1;
+        let url = archive_url(&lottery, cursor, 100 * days_left.min(PAGE_DAYS));
+        let body = CLIENT.get_text(&url).await?;
+        let fetched_at = Utc::now().timestamp();
+        let page: BichoData = serde_json::from_str(&body)?;
+        pages.push(RawPage { url, fetched_at, body });
 
-    let body = reqwest::get(url).await?.text().await?;
-    Ok(body)
+        let show_more = page.show_more;
+        let oldest = page.oldest_date();
+        let added = match merged.as_mut() {
+            Some(data) => data.merge(page),
+            None => {
+                let added = page.total_draws();
+                merged = Some(page);
+                added
+            }
+        };
+
+        let covered = match oldest {
+            Some(oldest) if show_more => ((to - oldest).num_days() as i32 + 1).min(total_days),
+            _ => total_days,
+        };
+        on_page(covered as usize, total_days as usize);
+
+        // Uma página sem sorteios novos seria pedida de novo para sempre
+        if added == 0 {
+            break;
+        }
+        if pages.len() >= MAX_PAGES {
+            println!("Limite de {} páginas atingido para a loteria '{}'", MAX_PAGES, lottery);
+            break;
+        }
+        match next_cursor(cursor, oldest, from, show_more) {
+            Some(next) => cursor = next,
+            None => break,
+        }
+    }
+
+    let json = serde_json::to_string(&merged.expect("ao menos uma página é baixada"))?;
+    Ok(FetchedDraws { json, pages })
+}
+
+// Data da próxima página, ou `None` se acabou. Também para se o site não
+// voltar no tempo (ignorou ou limitou a data pedida), senão a mesma página
+// seria pedida para sempre.
+fn next_cursor(
+    cursor: NaiveDate,
+    oldest: Option<NaiveDate>,
+    from: NaiveDate,
+    show_more: bool,
+) -> Option<NaiveDate> {
+    let oldest = oldest.filter(|&oldest| show_more && oldest > from)?;
+    // Um único dia maior que a página faria o cursor parar no lugar
+    let next = if oldest < cursor { oldest } else { oldest.pred_opt()? };
+
+    (next < cursor).then_some(next)
+}
+
+#[cfg(test)]
+mod testes {
+    use super::*;
+
+    #[test]
+    fn cursor_only_moves_backwards() {
+        let date = |day| NaiveDate::from_ymd_opt(2024, 3, day).unwrap();
+        let from = date(1);
+
+        assert_eq!(next_cursor(date(20), Some(date(12)), from, true), Some(date(12)));
+        // O dia todo na mesma página: pula para o anterior
+        assert_eq!(next_cursor(date(12), Some(date(12)), from, true), Some(date(11)));
+        assert_eq!(next_cursor(date(12), Some(date(12)), from, false), None);
+        assert_eq!(next_cursor(date(12), Some(from), from, true), None);
+        // O site devolveu uma página mais nova que a pedida
+        assert_eq!(next_cursor(date(12), Some(date(15)), from, true), None);
+        assert_eq!(next_cursor(date(12), Some(date(13)), from, true), None);
+        assert_eq!(next_cursor(date(12), None, from, true), None);
+    }
 }
@@ -0,0 +1,273 @@
+use super::conn::{self, DbLocation, DbPathSource};
+use super::jobs;
+use super::settings::{self, Settings};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Workspace que usa o banco principal, o de `conn::resolve_db_path`
+pub const MAIN_WORKSPACE: &str = "principal";
+
+// Pasta, dentro da pasta de dados do app, com um banco por workspace
+const WORKSPACES_DIR: &str = "workspaces";
+
+// Arquivos que o SQLite cria ao lado do banco e que andam junto com ele
+const SIDECAR_SUFFIXES: &[&str] = &["-journal", "-wal", "-shm"];
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Workspace {
+    pub name: String,
+    pub path: PathBuf,
+    pub active: bool,
+}
+
+// Pastas do app onde ficam os bancos e as configurações
+pub struct AppDirs {
+    pub data_dir: PathBuf,
+    pub config_dir: PathBuf,
+}
+
+// Nomes viram nomes de arquivo, então só letras, números, espaço, `-` e `_`
+fn validate_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    let valid_chars = name
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == ' ' || c == '-' || c == '_');
+
+    if name.is_empty() || name.chars().count() > 64 || !valid_chars {
+        return Err(format!(
+            "Nome de workspace inválido: '{}'. Use até 64 letras, números, espaços, '-' ou '_'",
+            name
+        ));
+    }
+
+    Ok(name.to_string())
+}
+
+fn is_main(name: &str) -> bool {
+    name.trim().eq_ignore_ascii_case(MAIN_WORKSPACE)
+}
+
+fn workspace_path(data_dir: &Path, name: &str) -> PathBuf {
+    data_dir.join(WORKSPACES_DIR).join(format!("{}.db", name))
+}
+
+fn sidecar_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(suffix);
+
+    PathBuf::from(sidecar)
+}
+
+fn location(dirs: &AppDirs, settings: &Settings, name: &str) -> DbLocation {
+    if is_main(name) {
+        return conn::resolve_db_path(&dirs.data_dir, settings);
+    }
+
+    DbLocation {
+        path: workspace_path(&dirs.data_dir, name),
+        source: DbPathSource::Workspace,
+    }
+}
+
+fn is_active(path: &Path) -> bool {
+    conn::db_location().is_some_and(|location| location.path == path)
+}
+
+fn workspace(name: &str, location: DbLocation) -> Workspace {
+    Workspace {
+        name: name.to_string(),
+        active: is_active(&location.path),
+        path: location.path,
+    }
+}
+
+// Banco do workspace aberto por último. Se ele não existir mais, usa o principal.
+pub fn active_location(dirs: &AppDirs, settings: &Settings) -> DbLocation {
+    if let Some(name) = settings.workspace.as_deref() {
+        let location = location(dirs, settings, name);
+        if location.path.exists() {
+            return location;
+        }
+
+        println!("O workspace '{}' não existe mais, usando o principal", name);
+    }
+
+    conn::resolve_db_path(&dirs.data_dir, settings)
+}
+
+// Workspace principal primeiro e depois os outros, por nome
+pub fn list(dirs: &AppDirs) -> Result<Vec<Workspace>, String> {
+    let settings = settings::load(&dirs.config_dir)?;
+    let mut workspaces = vec![workspace(MAIN_WORKSPACE, location(dirs, &settings, MAIN_WORKSPACE))];
+
+    let dir = dirs.data_dir.join(WORKSPACES_DIR);
+    if !dir.exists() {
+        return Ok(workspaces);
+    }
+
+    let mut names: Vec<String> = fs::read_dir(&dir)
+        .map_err(|err| err.to_string())?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            match path.extension() {
+                Some(extension) if extension == "db" => {
+                    path.file_stem().map(|stem| stem.to_string_lossy().to_string())
+                }
+                _ => None,
+            }
+        })
+        .collect();
+    names.sort();
+
+    for name in names {
+        let location = location(dirs, &settings, &name);
+        workspaces.push(workspace(&name, location));
+    }
+
+    Ok(workspaces)
+}
+
+pub fn create(dirs: &AppDirs, name: &str) -> Result<Workspace, String> {
+    if is_main(name) {
+        return Err(format!("O workspace '{}' já existe", MAIN_WORKSPACE));
+    }
+    let name = validate_name(name)?;
+    let path = workspace_path(&dirs.data_dir, &name);
+    if path.exists() {
+        return Err(format!("O workspace '{}' já existe", name));
+    }
+
+    // Cria o arquivo já com o esquema atual, sem trocar o banco em uso
+    conn::prepare_db(&path)?;
+
+    Ok(workspace(
+        &name,
+        DbLocation {
+            path,
+            source: DbPathSource::Workspace,
+        },
+    ))
+}
+
+// Passa a usar o banco do workspace, sem reiniciar o app
+pub fn switch(dirs: &AppDirs, name: &str) -> Result<Workspace, String> {
+    if jobs::has_running_jobs() {
+        return Err("Espere as sincronizações em andamento terminarem para trocar de workspace".to_string());
+    }
+
+    let mut settings = settings::load(&dirs.config_dir)?;
+    let name = if is_main(name) {
+        MAIN_WORKSPACE.to_string()
+    } else {
+        validate_name(name)?
+    };
+    let location = location(dirs, &settings, &name);
+    if location.source == DbPathSource::Workspace && !location.path.exists() {
+        return Err(format!("O workspace '{}' não existe", name));
+    }
+
+    conn::open_db(location.clone())?;
+    settings.workspace = if is_main(&name) { None } else { Some(name.clone()) };
+    settings::save(&dirs.config_dir, &settings)?;
+
+    Ok(workspace(&name, location))
+}
+
+pub fn rename(dirs: &AppDirs, name: &str, new_name: &str) -> Result<Workspace, String> {
+    if is_main(name) || is_main(new_name) {
+        return Err(format!("O workspace '{}' não pode ser renomeado", MAIN_WORKSPACE));
+    }
+    let name = validate_name(name)?;
+    let new_name = validate_name(new_name)?;
+
+    let path = workspace_path(&dirs.data_dir, &name);
+    let new_path = workspace_path(&dirs.data_dir, &new_name);
+    if !path.exists() {
+        return Err(format!("O workspace '{}' não existe", name));
+    }
+    if new_path.exists() {
+        return Err(format!("O workspace '{}' já existe", new_name));
+    }
+    if is_active(&path) {
+        return Err("Troque para outro workspace antes de renomear este".to_string());
+    }
+
+    fs::rename(&path, &new_path).map_err(|err| err.to_string())?;
+    for suffix in SIDECAR_SUFFIXES {
+        let sidecar = sidecar_path(&path, suffix);
+        if sidecar.exists() {
+            fs::rename(&sidecar, sidecar_path(&new_path, suffix)).map_err(|err| err.to_string())?;
+        }
+    }
+
+    Ok(workspace(
+        &new_name,
+        DbLocation {
+            path: new_path,
+            source: DbPathSource::Workspace,
+        },
+    ))
+}
+
+pub fn delete(dirs: &AppDirs, name: &str) -> Result<(), String> {
+    if is_main(name) {
+        return Err(format!("O workspace '{}' não pode ser apagado", MAIN_WORKSPACE));
+    }
+    let name = validate_name(name)?;
+
+    let path = workspace_path(&dirs.data_dir, &name);
+    if !path.exists() {
+        return Err(format!("O workspace '{}' não existe", name));
+    }
+    if is_active(&path) {
+        return Err("Troque para outro workspace antes de apagar este".to_string());
+    }
+
+    fs::remove_file(&path).map_err(|err| err.to_string())?;
+    for suffix in SIDECAR_SUFFIXES {
+        let sidecar = sidecar_path(&path, suffix);
+        if sidecar.exists() {
+            fs::remove_file(&sidecar).map_err(|err| err.to_string())?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod testes {
+    use super::*;
+
+    #[test]
+    fn workspace_names_are_safe_file_names() {
+        assert_eq!(validate_name(" Produção 2024 ").unwrap(), "Produção 2024");
+        assert!(validate_name("").is_err());
+        assert!(validate_name("../fora").is_err());
+        assert!(validate_name("a/b").is_err());
+    }
+
+    #[test]
+    fn create_rename_and_delete_workspace() {
+        let root = std::env::temp_dir().join(format!("get_bicho_workspaces_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let dirs = AppDirs {
+            data_dir: root.join("dados"),
+            config_dir: root.join("config"),
+        };
+
+        create(&dirs, "testes").unwrap();
+        assert!(create(&dirs, "testes").is_err());
+        assert!(create(&dirs, "Principal").is_err());
+
+        rename(&dirs, "testes", "experimentos").unwrap();
+        let names: Vec<String> = list(&dirs).unwrap().into_iter().map(|w| w.name).collect();
+        assert_eq!(names, vec![MAIN_WORKSPACE.to_string(), "experimentos".to_string()]);
+
+        delete(&dirs, "experimentos").unwrap();
+        assert!(delete(&dirs, "experimentos").is_err());
+        assert!(delete(&dirs, MAIN_WORKSPACE).is_err());
+        assert_eq!(list(&dirs).unwrap().len(), 1);
+    }
+}
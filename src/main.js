@@ -19,10 +19,56 @@ async function get_houses() {
   }
 }
 
-listen("progress", (event) => {
-  setProgress(event.payload)
+// Cada sincronização envia o progresso no evento `progress:<job_id>`
+const job_listeners = {};
+
+listen("job-started", async (event) => {
+  const job_id = event.payload.job_id;
+
+  job_listeners[job_id] = await listen(`progress:${job_id}`, (progress_event) => {
+    const progress = progress_event.payload;
+    renderJobProgress(progress);
+
+    if (progress.phase === "analyze" && progress.processed === progress.total) {
+      job_listeners[job_id]();
+      delete job_listeners[job_id];
+    }
+  });
 });
 
+// Jobs que falham ou são cancelados não chegam ao fim da fase "analyze",
+// então os ouvintes são removidos também quando o job termina
+async function releaseFinishedJobListeners() {
+  try {
+    const jobs = await invoke("list_jobs", {});
+    const running = jobs.filter(job => job.status === "running").map(job => String(job.id));
+
+    for (const job_id of Object.keys(job_listeners)) {
+      if (!running.includes(job_id)) {
+        job_listeners[job_id]();
+        delete job_listeners[job_id];
+      }
+    }
+  } catch (error) {
+    console.error("Erro ao listar os jobs:", error);
+  }
+}
+
+function renderJobProgress(progress) {
+  const phases = { download: "Baixando", parse: "Lendo", insert: "Salvando", analyze: "Finalizando" };
+  const loadingMessage = document.getElementById("loadingMessage");
+  const percent = progress.total > 0 ? Math.floor(progress.processed / progress.total * 100) : 0;
+
+  if (loadingMessage) {
+    const eta = progress.eta_secs ? ` (~${Math.ceil(progress.eta_secs)}s)` : "";
+    loadingMessage.innerHTML = `${progress.house}: ${phases[progress.phase]} ${progress.processed}/${progress.total}${eta}`;
+  }
+
+  if (progress.phase === "insert") {
+    setProgress(percent);
+  }
+}
+
 let houses_in_progress = [];
 
 window.get_bicho_data = async function (option_element = undefined) {
@@ -47,20 +93,13 @@ window.get_bicho_data = async function (option_element = undefined) {
     houses_in_progress.push(lottery);
 
     const get_all_time_data = document.getElementById("all-time-switch").checked;
-    const last_update = document.getElementById("last-updated").innerHTML;
-    const timestamp = dateToTimestamp(last_update);
-    let days_without_updates = getDaysDifference(timestamp);
-    
-    if (days_without_updates > 1600 || get_all_time_data) {
-      days_without_updates = 1600;
-    }
-    
+
     showLoading(`Carregando dados da casa ${house_name}...`);
-    // await invoke("get_bichos_data", {
-    //   houseName: house_name,
-    //   lottery: lottery,
-    //   totalDays: days_without_updates
-    // });
+    await invoke("sync_house", {
+      houseName: house_name,
+      lottery: lottery,
+      full: get_all_time_data
+    });
 
     showNotification("Dados da casa carregados com sucesso.", "success");
     window.get_table_info()
@@ -68,23 +107,60 @@ window.get_bicho_data = async function (option_element = undefined) {
     showNotification(`Erro ao obter dados da casa: ${error.message}`, "danger");
   } finally {
     houses_in_progress = houses_in_progress.filter(item => item !== lottery);
+    releaseFinishedJobListeners();
+    hideLoading();
+  }
+};
+
+window.get_all_houses_bicho_data = async function () {
+  const get_all_time_data = document.getElementById("all-time-switch").checked;
+
+  showLoading("Carregando dados de todas as casas...");
+  try {
+    const results = await invoke("sync_all_houses", { full: get_all_time_data });
+
+    for (const result of results) {
+      if (result.error) {
+        showNotification(`Erro na casa ${result.house}: ${result.error}`, "danger");
+      }
+    }
+
+    const inserted = results.reduce((total, result) => total + result.inserted, 0);
+    showNotification(`${inserted} sorteios salvos em ${results.length} casas.`, "success");
+    window.get_table_info()
+  } catch (error) {
+    showNotification(`Erro ao obter dados das casas: ${error.message}`, "danger");
+  } finally {
+    releaseFinishedJobListeners();
     hideLoading();
   }
 };
 
+window.cancel_running_jobs = async function () {
+  try {
+    const jobs = await invoke("list_jobs", {});
+    for (const job of jobs.filter(job => job.status === "running")) {
+      await invoke("cancel_job", { id: job.id });
+    }
+    showNotification("Sincronização cancelada.", "warning");
+  } catch (error) {
+    showNotification("Erro ao cancelar: " + error.message, "danger");
+  }
+};
+
 window.get_table_info = async function () {
   showLoading("Carregando informações da tabela...");
   try {
     const select_element = document.getElementById("house-selector");
-    const house_name = select_element.options[select_element.selectedIndex].innerHTML;
+    const lottery = select_element.value;
 
-    if (house_name === "invalid") return;
+    if (lottery === "invalid") return;
 
-    const database_info = JSON.parse(await invoke("get_database_info", { houseName: house_name }));
+    const database_info = JSON.parse(await invoke("get_database_info", { lottery }));
     document.querySelector("header > div:nth-child(1) > span").innerHTML = database_info.total_rows;
     document.querySelector("header > div:nth-child(2) > span").innerHTML = timestampToDate(database_info.date) || "O banco de dados está vazio";
-    await get_groups(house_name);
-    await get_loss_sequence(house_name);
+    await get_groups(lottery);
+    await get_loss_sequence(lottery);
     showNotification("Informações da tabela carregadas com sucesso.", "success");
   } catch (error) {
     showNotification("Erro ao obter informações da tabela: " + error.message, "danger");
@@ -101,6 +177,10 @@ window.addEventListener("DOMContentLoaded", () => {
       const option = document.createElement("option");
       option.value = house.value;
       option.innerHTML = house.name;
+      if (house.disappeared) {
+        option.title = "Casa fora do site, apenas dados salvos";
+        option.classList.add("text-muted");
+      }
       houseSelect.appendChild(option);
     });
   });
@@ -125,7 +205,7 @@ window.export_csv = async function () {
     });
 
     if (filePath) {
-      await invoke("export_csv", { houseName: house_name, filePath });
+      await invoke("export_csv", { lottery: option_element.value, filePath });
       showNotification(`Arquivo salvo em: ${filePath}`, "success");
     } else {
       showNotification(`O usuário cancelou o diálogo.`, "danger");
@@ -136,38 +216,38 @@ window.export_csv = async function () {
   }
 };
 
-window.get_hours = async function (house_name) {
+window.get_hours = async function (lottery) {
   try {
-    return await invoke("get_hours", { houseName: house_name });
+    return await invoke("get_hours", { lottery });
   } catch (error) {
     showNotification("Erro ao obter horários: " + error.message, "danger");
     return [];
   }
 };
 
-window.get_places = async function (house_name) {
+window.get_places = async function (lottery) {
   try {
-    return await invoke("get_places", { houseName: house_name });
+    return await invoke("get_places", { lottery });
   } catch (error) {
     showNotification("Erro ao obter prêmios: " + error.message, "danger");
     return [];
   }
 };
 
-window.add_group = async function (house_name, data) {
+window.add_group = async function (lottery, data) {
   try {
-    await invoke("add_group", { houseName: house_name, data });
-    await window.get_groups(house_name);
-    get_loss_sequence(house_name);
+    await invoke("add_group", { lottery, data });
+    await window.get_groups(lottery);
+    get_loss_sequence(lottery);
     showNotification("Grupo adicionado com sucesso.", "success");
   } catch (error) {
     showNotification("Erro ao adicionar grupo: " + error.message, "danger");
   }
 };
 
-window.get_groups = async function (house_name) {
+window.get_groups = async function (lottery) {
   try {
-    const groups = JSON.parse(await invoke("get_group", { houseName: house_name }));
+    const groups = JSON.parse(await invoke("get_group", { lottery }));
     renderTable(groups);
     return groups;
   } catch (error) {
@@ -176,11 +256,11 @@ window.get_groups = async function (house_name) {
   }
 };
 
-window.update_group = async function (house_name, data) {
+window.update_group = async function (lottery, data) {
   try {
-    await invoke("edit_group", { houseName: house_name, data });
-    await window.get_groups(house_name);
-    get_loss_sequence(house_name);
+    await invoke("edit_group", { lottery, data });
+    await window.get_groups(lottery);
+    get_loss_sequence(lottery);
     showNotification("Grupo atualizado com sucesso.", "success");
   } catch (error) {
     showNotification("Erro ao atualizar grupo: " + error.message, "danger");
@@ -190,21 +270,21 @@ window.update_group = async function (house_name, data) {
 
 window.delete_group = async function (id) {
   const select_element = document.getElementById("house-selector");
-  const house_name = select_element.options[select_element.selectedIndex].innerHTML;
+  const lottery = select_element.value;
   
   try {
-    await invoke("delete_group", { houseName: house_name, id: parseInt(id) });
-    await window.get_groups(house_name);
-    get_loss_sequence(house_name);
+    await invoke("delete_group", { lottery, id: parseInt(id) });
+    await window.get_groups(lottery);
+    get_loss_sequence(lottery);
     showNotification("Grupo deletado com sucesso.", "success");
   } catch (error) {
     showNotification("Erro ao excluir grupo: " + error.message, "danger");
   }
 };
 
-async function get_loss_sequence(house_name) {
+async function get_loss_sequence(lottery) {
   try {
-    const loss_sequences = await invoke("get_loss_sequence", { houseName: house_name });
+    const loss_sequences = await invoke("get_loss_sequence", { lottery });
     renderRecommendations(loss_sequences);
   } catch (error) {
     showNotification("Erro ao obter sequência de derrota: " + error.message, "danger");
@@ -217,7 +297,9 @@ function renderRecommendations(recommendations) {
 
   tableBody.innerHTML = "";
 
-  const sortedRecommendations = recommendations.sort((a, b) => b.loss_sequence - a.loss_sequence);
+  // As secas mais raras primeiro, não as mais longas. A surpresa usa a chance
+  // histórica quando a casa tem secas suficientes, senão a teórica.
+  const sortedRecommendations = recommendations.sort((a, b) => b.surprise - a.surprise);
 
   for (const rec of sortedRecommendations) {
     const row = document.createElement("tr");
@@ -228,6 +310,11 @@ function renderRecommendations(recommendations) {
     lossSeqCell.classList.add(cellClass);
     row.appendChild(lossSeqCell);
 
+    const chanceCell = document.createElement("td");
+    chanceCell.textContent = `${formatChance(rec.theoretical_probability)} / ${formatChance(rec.empirical_probability)}`;
+    chanceCell.classList.add(cellClass);
+    row.appendChild(chanceCell);
+
     const hourCell = document.createElement("td");
     hourCell.textContent = rec.hour;
     hourCell.classList.add(cellClass);
@@ -247,11 +334,11 @@ function renderRecommendations(recommendations) {
   }
 }
 
-function getDaysDifference(timestamp) {
-  const dateFromTimestamp = new Date(timestamp * 1000);
-  const currentDate = new Date();
-  const differenceInMillis = currentDate - dateFromTimestamp;
-  return Math.floor(differenceInMillis / (1000 * 60 * 60 * 24));
+function formatChance(probability) {
+  if (probability === null || probability === undefined) {
+    return "-";
+  }
+  return `${(probability * 100).toFixed(2)}%`;
 }
 
 function timestampToDate(timestamp) {
@@ -262,12 +349,6 @@ function timestampToDate(timestamp) {
   return `${day}-${month}-${year}`;
 }
 
-function dateToTimestamp(dateString) {
-  const [day, month, year] = dateString.split('-');
-  const date = new Date(year, month - 1, day);
-  return Math.floor(date.getTime() / 1000);
-}
-
 function renderTable(groups) {
   const tableBody = document.querySelector("#groupTable tbody");
   tableBody.innerHTML = "";
@@ -311,6 +392,7 @@ window.showLoading = function(message = "Carregando...") {
       </div>
     </div>
     <span class="ms-3" id="loadingMessage">${message}</span>
+    <button class="btn btn-sm btn-outline-danger ms-3" onclick="window.cancel_running_jobs()">Cancelar</button>
   `;
   loadingBox.id = "loadingBox";
   document.body.appendChild(loadingBox);
@@ -357,9 +439,4 @@ window.setProgress = function(percent) {
   percent = Math.min(100, Math.max(0, percent)); // Limita entre 0 e 100
   progressBar.style.width = `${percent}%`;
   progressBar.textContent = `${percent}%`;
-
-  // Finaliza e esconde o carregamento automaticamente se atingir 100%
-  if (percent === 100) {
-    hideLoading()
-  }
 }
\ No newline at end of file